@@ -1,4 +1,42 @@
 mod libs;
 
+pub use libs::Blob;
+pub use libs::ChunkState;
+pub use libs::Comparison;
 pub use libs::DownloadError;
+pub use libs::DownloadHandle;
+pub use libs::DownloadManager;
 pub use libs::Downloader;
+pub use libs::Event;
+pub use libs::HubFile;
+pub use libs::Member;
+pub use libs::Notifier;
+pub use libs::Outcome;
+pub use libs::Plan;
+pub use libs::Policy;
+pub use libs::Preemption;
+pub use libs::Priority;
+pub use libs::ProgressSink;
+pub use libs::Queued;
+pub use libs::Quota;
+pub use libs::Record;
+pub use libs::Sandbox;
+pub use libs::Scanner;
+pub use libs::State;
+pub use libs::Vars;
+pub use libs::load_cookies;
+pub use libs::{Action, Actions, Category};
+pub use libs::{Algorithm, Checksum, Hasher};
+pub use libs::{
+    ArtifactDir, Artifacts, Auth, ChunkOrder, ChunkStrategy, Collision, DownloaderConfig,
+    HostOverride, OnFailure, Protocol, ProxyConfig, ProxyMode,
+};
+pub use libs::{Entry, crawl, mirror_path};
+pub use libs::{Forge, Release};
+pub use libs::{Format, Units};
+#[cfg(feature = "scripting")]
+pub use libs::{Hooks, Verdict};
+pub use libs::{Plugin, Resolved, resolve};
+pub use libs::{Progress, ProgressKind, Status};
+pub use libs::{Sample, SpeedTest, TEST_URL};
+pub use libs::{glob, safe_join, sanitize};
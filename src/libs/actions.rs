@@ -0,0 +1,261 @@
+use super::{Category, DownloadError, Vars, zip::extract_zip};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+use tokio::process::Command;
+
+// How deep `NextToVideo` looks below each folder it searches
+const SEARCH_DEPTH: usize = 3;
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Open,
+    Run(String),
+    Move(String),
+    Extract(String),
+    /// Moves subtitles beside the video in these folders whose name they
+    /// start with, e.g. `Movie.en.srt` next to `Movie.mkv`.
+    NextToVideo(Vec<String>),
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct Actions(HashMap<String, Vec<Action>>);
+
+impl Actions {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DownloadError> {
+        serde_json::from_slice(&std::fs::read(path)?)
+            .map_err(|e| DownloadError::Action(format!("invalid actions: {}", e)))
+    }
+
+    pub async fn run(&self, vars: &mut Vars) -> Result<(), DownloadError> {
+        let category = vars.get("category").unwrap_or_default().to_owned();
+        for action in self.0.get(&category).into_iter().flatten() {
+            action.run(vars).await?;
+        }
+        Ok(())
+    }
+}
+
+impl Action {
+    async fn run(&self, vars: &mut Vars) -> Result<(), DownloadError> {
+        let path = vars.get("path").unwrap_or_default().to_owned();
+        match self {
+            Self::Open => spawn(&opener(&path)).await,
+            Self::Run(line) => {
+                // Split before rendering so a path with spaces stays one argument
+                let words =
+                    shell_words::split(line).map_err(|e| DownloadError::Action(e.to_string()))?;
+                spawn(&words.iter().map(|p| vars.render(p)).collect::<Vec<_>>()).await
+            }
+            Self::Move(dir) => {
+                let dir = vars.render(dir);
+                tokio::fs::create_dir_all(&dir).await?;
+                let name = Path::new(&path).file_name().unwrap_or_default();
+                let target = Path::new(&dir).join(name);
+                relocate(Path::new(&path), &target).await?;
+                vars.set("path", target.to_string_lossy());
+                Ok(())
+            }
+            Self::Extract(dir) => {
+                let dir = vars.render(dir);
+                tokio::fs::create_dir_all(&dir).await?;
+                let extension = Path::new(&path)
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .unwrap_or_default()
+                    .to_ascii_lowercase();
+                match extension.as_str() {
+                    "zip" => extract_zip(path.as_ref(), dir.as_ref()).await,
+                    "7z" | "rar" => {
+                        spawn(&[
+                            "7z".to_owned(),
+                            "x".to_owned(),
+                            "-y".to_owned(),
+                            format!("-o{}", dir),
+                            path,
+                        ])
+                        .await
+                    }
+                    _ => {
+                        spawn(&[
+                            "tar".to_owned(),
+                            "-xf".to_owned(),
+                            path,
+                            "-C".to_owned(),
+                            dir,
+                        ])
+                        .await
+                    }
+                }
+            }
+            Self::NextToVideo(dirs) => {
+                let source = Path::new(&path);
+                let dirs: Vec<PathBuf> = dirs.iter().map(|d| vars.render(d).into()).collect();
+                let Some(video) = tokio::task::spawn_blocking({
+                    let source = source.to_path_buf();
+                    move || matching_video(&source, &dirs)
+                })
+                .await?
+                else {
+                    return Ok(());
+                };
+                let name = source.file_name().unwrap_or_default();
+                let target = video.with_file_name(name);
+                relocate(source, &target).await?;
+                vars.set("path", target.to_string_lossy());
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Renames `from` to `to`, copying when they are on different filesystems.
+async fn relocate(from: &Path, to: &Path) -> Result<(), DownloadError> {
+    match tokio::fs::rename(from, to).await {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            tokio::fs::copy(from, to).await?;
+            Ok(tokio::fs::remove_file(from).await?)
+        }
+        result => Ok(result?),
+    }
+}
+
+/// The video under `dirs` with the longest name that `subtitle` starts with.
+fn matching_video(subtitle: &Path, dirs: &[PathBuf]) -> Option<PathBuf> {
+    let stem = subtitle.file_stem()?.to_string_lossy().to_lowercase();
+    let mut best: Option<(usize, PathBuf)> = None;
+    let mut pending: Vec<_> = dirs.iter().map(|d| (d.clone(), 0)).collect();
+    while let Some((dir, depth)) = pending.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                if depth < SEARCH_DEPTH {
+                    pending.push((path, depth + 1));
+                }
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if Category::from_name(&name) != Category::Video {
+                continue;
+            }
+            let Some(video) = path.file_stem().map(|s| s.to_string_lossy().to_lowercase()) else {
+                continue;
+            };
+            let matches = stem == video
+                || stem
+                    .strip_prefix(&video)
+                    .is_some_and(|rest| rest.starts_with('.'));
+            if matches && best.as_ref().is_none_or(|(len, _)| video.len() > *len) {
+                best = Some((video.len(), path));
+            }
+        }
+    }
+    best.map(|(_, path)| path)
+}
+
+fn opener(path: &str) -> Vec<String> {
+    let command: &[&str] = if cfg!(windows) {
+        &["cmd", "/C", "start", ""]
+    } else if cfg!(target_os = "macos") {
+        &["open"]
+    } else {
+        &["xdg-open"]
+    };
+    command
+        .iter()
+        .map(|s| s.to_string())
+        .chain([path.to_owned()])
+        .collect()
+}
+
+async fn spawn(command: &[String]) -> Result<(), DownloadError> {
+    let Some((program, args)) = command.split_first() else {
+        return Ok(());
+    };
+    let status = Command::new(program)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await?;
+    status
+        .success()
+        .then_some(())
+        .ok_or_else(|| DownloadError::Action(format!("{} exited with {}", program, status)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::libs::zip::fixtures::{scratch, zip};
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn run_keeps_quoted_and_rendered_arguments_whole() {
+        let dir = scratch("run");
+        let source = dir.join("my file.txt");
+        std::fs::write(&source, b"x").unwrap();
+        let mut vars = Vars::new().with("path", source.to_string_lossy());
+        Action::Run("cp {path} \"{path}.copy of\"".to_owned())
+            .run(&mut vars)
+            .await
+            .unwrap();
+        assert!(dir.join("my file.txt.copy of").exists());
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn finds_the_longest_matching_video() {
+        let dir = scratch("videos");
+        std::fs::create_dir_all(dir.join("Show/Season 1")).unwrap();
+        for name in [
+            "Show/Season 1/Show.S01E02.mkv",
+            "Show.mp4",
+            "Show.S01E02.nfo",
+        ] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+        let dirs = [dir.clone()];
+        let found = matching_video("Show.S01E02.en.srt".as_ref(), &dirs);
+        assert_eq!(found, Some(dir.join("Show/Season 1/Show.S01E02.mkv")));
+        assert_eq!(matching_video("Other.srt".as_ref(), &dirs), None);
+        assert_eq!(matching_video("Showcase.srt".as_ref(), &dirs), None);
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn extracts_zip_without_escaping() {
+        let dir = scratch("unzip");
+        let archive = dir.join("a.zip");
+        std::fs::write(
+            &archive,
+            zip(&[
+                ("docs/readme.txt", 0, b"hello", 5),
+                ("../../evil.txt", 0, b"x", 1),
+            ]),
+        )
+        .unwrap();
+        let out = dir.join("out");
+        tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(
+                Action::Extract(out.to_string_lossy().into_owned())
+                    .run(&mut Vars::new().with("path", archive.to_string_lossy())),
+            )
+            .unwrap();
+        assert_eq!(
+            std::fs::read(out.join("docs/readme.txt")).unwrap(),
+            b"hello"
+        );
+        assert!(out.join("evil.txt").exists());
+        assert!(!dir.join("evil.txt").exists());
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
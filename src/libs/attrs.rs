@@ -0,0 +1,72 @@
+use std::path::Path;
+
+#[cfg(windows)]
+mod imp {
+    use std::{iter::once, os::windows::ffi::OsStrExt, path::Path};
+    pub use windows_sys::Win32::Storage::FileSystem::{
+        FILE_ATTRIBUTE_HIDDEN as HIDDEN, FILE_ATTRIBUTE_NOT_CONTENT_INDEXED as NOT_INDEXED,
+        FILE_ATTRIBUTE_TEMPORARY as TEMPORARY,
+    };
+    use windows_sys::Win32::Storage::FileSystem::{
+        FILE_ATTRIBUTE_NORMAL, GetFileAttributesW, INVALID_FILE_ATTRIBUTES, SetFileAttributesW,
+    };
+
+    pub fn update(path: &Path, set: u32, clear: u32) {
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(once(0)).collect();
+        unsafe {
+            let current = GetFileAttributesW(wide.as_ptr());
+            if current != INVALID_FILE_ATTRIBUTES {
+                let attrs = match (current | set) & !clear {
+                    0 => FILE_ATTRIBUTE_NORMAL,
+                    attrs => attrs,
+                };
+                SetFileAttributesW(wide.as_ptr(), attrs);
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+pub fn mark_in_progress(path: &Path, no_index: bool) {
+    let no_index = if no_index { imp::NOT_INDEXED } else { 0 };
+    imp::update(path, imp::TEMPORARY | no_index, 0);
+}
+
+#[cfg(windows)]
+pub fn mark_finished(path: &Path) {
+    imp::update(path, 0, imp::TEMPORARY | imp::NOT_INDEXED);
+}
+
+/// Zone.Identifier contents a browser would write for a file from `host_url`.
+pub fn zone_identifier(host_url: &str, referrer: Option<&str>) -> String {
+    let mut zone = "[ZoneTransfer]\r\nZoneId=3\r\n".to_owned();
+    if let Some(referrer) = referrer {
+        zone += &format!("ReferrerUrl={}\r\n", referrer);
+    }
+    zone += &format!("HostUrl={}\r\n", host_url);
+    zone
+}
+
+#[cfg(windows)]
+pub fn mark_of_the_web(path: &Path, zone: &str) {
+    let mut stream = path.as_os_str().to_owned();
+    stream.push(":Zone.Identifier");
+    std::fs::write(stream, zone).ok();
+}
+
+#[cfg(windows)]
+pub fn hide(path: &Path) {
+    imp::update(path, imp::HIDDEN | imp::NOT_INDEXED, 0);
+}
+
+#[cfg(not(windows))]
+pub fn mark_in_progress(_: &Path, _: bool) {}
+
+#[cfg(not(windows))]
+pub fn mark_finished(_: &Path) {}
+
+#[cfg(not(windows))]
+pub fn mark_of_the_web(_: &Path, _: &str) {}
+
+#[cfg(not(windows))]
+pub fn hide(_: &Path) {}
@@ -0,0 +1,138 @@
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Category {
+    Video,
+    Audio,
+    Image,
+    Archive,
+    DiskImage,
+    Document,
+    Subtitles,
+    Executable,
+    Other,
+}
+
+const EXTENSIONS: [(Category, &[&str]); 8] = [
+    (
+        Category::Video,
+        &[
+            "mp4", "mkv", "avi", "mov", "webm", "flv", "wmv", "m4v", "ts",
+        ],
+    ),
+    (
+        Category::Audio,
+        &["mp3", "flac", "wav", "ogg", "m4a", "aac", "opus", "wma"],
+    ),
+    (
+        Category::Image,
+        &["jpg", "jpeg", "png", "gif", "webp", "bmp", "svg", "tiff"],
+    ),
+    (
+        Category::Archive,
+        &["zip", "rar", "7z", "tar", "gz", "xz", "bz2", "zst", "tgz"],
+    ),
+    (
+        Category::DiskImage,
+        &["iso", "img", "dmg", "vhd", "vhdx", "qcow2"],
+    ),
+    (
+        Category::Document,
+        &[
+            "pdf", "doc", "docx", "xls", "xlsx", "ppt", "pptx", "txt", "epub",
+        ],
+    ),
+    (Category::Subtitles, &["srt", "ass", "ssa", "vtt", "sub"]),
+    (
+        Category::Executable,
+        &["exe", "msi", "apk", "deb", "rpm", "appimage", "bat", "sh"],
+    ),
+];
+
+impl Category {
+    pub fn from_name(name: &str) -> Self {
+        let extension = Path::new(name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        EXTENSIONS
+            .iter()
+            .find(|(_, exts)| exts.contains(&extension.as_str()))
+            .map_or(Self::Other, |&(category, _)| category)
+    }
+
+    pub fn from_mime(mime: &str) -> Self {
+        let mime = mime.split(';').next().unwrap_or_default().trim();
+        let mime = mime.to_ascii_lowercase();
+        match mime.split_once('/').unwrap_or_default() {
+            ("video", _) => Self::Video,
+            ("audio", _) => Self::Audio,
+            ("image", _) => Self::Image,
+            ("text", "vtt") => Self::Subtitles,
+            ("text", _) => Self::Document,
+            (
+                "application",
+                "zip" | "gzip" | "x-gzip" | "x-tar" | "x-xz" | "x-bzip2" | "zstd"
+                | "x-7z-compressed" | "vnd.rar" | "x-rar-compressed",
+            ) => Self::Archive,
+            ("application", "x-iso9660-image" | "x-apple-diskimage") => Self::DiskImage,
+            ("application", "pdf" | "msword" | "epub+zip" | "rtf") => Self::Document,
+            ("application", sub) if sub.starts_with("vnd.openxmlformats") => Self::Document,
+            ("application", sub) if sub.starts_with("vnd.ms-") => Self::Document,
+            (
+                "application",
+                "x-msdownload"
+                | "x-msi"
+                | "vnd.microsoft.portable-executable"
+                | "vnd.android.package-archive"
+                | "vnd.debian.binary-package"
+                | "x-rpm"
+                | "x-executable"
+                | "x-sh",
+            ) => Self::Executable,
+            _ => Self::Other,
+        }
+    }
+
+    /// Goes by the file extension, falling back to the server's Content-Type
+    /// when the name says nothing.
+    pub fn detect(name: &str, mime: Option<&str>) -> Self {
+        match Self::from_name(name) {
+            Self::Other => mime.map_or(Self::Other, Self::from_mime),
+            category => category,
+        }
+    }
+
+    /// Inverse of [`Category::as_str`], ignoring case.
+    pub fn parse(name: &str) -> Option<Self> {
+        [
+            Self::Video,
+            Self::Audio,
+            Self::Image,
+            Self::Archive,
+            Self::DiskImage,
+            Self::Document,
+            Self::Subtitles,
+            Self::Executable,
+            Self::Other,
+        ]
+        .into_iter()
+        .find(|c| c.as_str().eq_ignore_ascii_case(name))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Video => "Video",
+            Self::Audio => "Audio",
+            Self::Image => "Image",
+            Self::Archive => "Archive",
+            Self::DiskImage => "DiskImage",
+            Self::Document => "Document",
+            Self::Subtitles => "Subtitles",
+            Self::Executable => "Executable",
+            Self::Other => "Other",
+        }
+    }
+}
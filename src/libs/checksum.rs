@@ -0,0 +1,188 @@
+use super::DownloadError;
+use sha2::Digest;
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    Sha256,
+    Md5,
+    Blake3,
+}
+
+#[derive(Clone, Debug)]
+pub struct Checksum {
+    pub algorithm: Algorithm,
+    pub expected: String,
+}
+
+impl Checksum {
+    pub fn new(algorithm: Algorithm, expected: &str) -> Self {
+        Self {
+            algorithm,
+            expected: expected.trim().to_ascii_lowercase(),
+        }
+    }
+
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (name, digest) = spec.split_once([':', '='])?;
+        let algorithm = match name.trim().to_ascii_lowercase().replace('-', "").as_str() {
+            "sha256" => Algorithm::Sha256,
+            "md5" => Algorithm::Md5,
+            "blake3" => Algorithm::Blake3,
+            _ => return None,
+        };
+        Some(Self::new(algorithm, digest))
+    }
+
+    pub fn check(&self, actual: String) -> Result<(), DownloadError> {
+        match actual == self.expected {
+            true => Ok(()),
+            false => Err(DownloadError::ChecksumMismatch {
+                expected: self.expected.clone(),
+                actual,
+            }),
+        }
+    }
+
+    pub async fn verify(&self, path: impl Into<PathBuf>) -> Result<(), DownloadError> {
+        let path = path.into();
+        let algorithm = self.algorithm;
+        let actual = tokio::task::spawn_blocking(move || {
+            let mut file = File::open(path)?;
+            let mut hasher = Hasher::new(algorithm);
+            let mut buf = vec![0; 1 << 20];
+            loop {
+                match file.read(&mut buf)? {
+                    0 => break,
+                    n => hasher.update(&buf[..n]),
+                }
+            }
+            Ok::<_, std::io::Error>(hasher.finalize())
+        })
+        .await??;
+        self.check(actual)
+    }
+}
+
+/// Hashes a download while it is written: bytes arriving in file order are
+/// fed straight from memory, and ranges that landed out of order are read
+/// back once everything before them is complete. Always takes the SHA-256,
+/// plus the expected checksum's algorithm when that is another one.
+pub(super) struct Incremental {
+    checksum: Option<Checksum>,
+    path: PathBuf,
+    inner: Arc<Mutex<(Vec<Hasher>, u64)>>,
+}
+
+impl Incremental {
+    pub fn new(checksum: Option<Checksum>, path: impl Into<PathBuf>) -> Self {
+        let this = Self {
+            checksum,
+            path: path.into(),
+            inner: Arc::default(),
+        };
+        this.reset();
+        this
+    }
+
+    fn hashers(&self) -> Vec<Hasher> {
+        let extra = self
+            .checksum
+            .as_ref()
+            .map(|c| c.algorithm)
+            .filter(|&a| a != Algorithm::Sha256);
+        [Algorithm::Sha256]
+            .into_iter()
+            .chain(extra)
+            .map(Hasher::new)
+            .collect()
+    }
+
+    pub fn feed(&self, offset: u64, data: &[u8]) {
+        if let Ok(mut inner) = self.inner.try_lock()
+            && inner.1 == offset
+        {
+            inner.0.iter_mut().for_each(|h| h.update(data));
+            inner.1 += data.len() as u64;
+        }
+    }
+
+    pub fn reset(&self) {
+        *self.inner.lock().unwrap() = (self.hashers(), 0);
+    }
+
+    pub async fn catch_up(&self, upto: u64) -> Result<(), DownloadError> {
+        let (inner, path) = (self.inner.clone(), self.path.clone());
+        tokio::task::spawn_blocking(move || {
+            let mut inner = inner.lock().unwrap();
+            if inner.1 >= upto {
+                return Ok(());
+            }
+            let mut file = File::open(path)?;
+            file.seek(SeekFrom::Start(inner.1))?;
+            let mut buf = vec![0; 1 << 20];
+            while inner.1 < upto {
+                let n = buf.len().min((upto - inner.1) as usize);
+                file.read_exact(&mut buf[..n])?;
+                inner.0.iter_mut().for_each(|h| h.update(&buf[..n]));
+                inner.1 += n as u64;
+            }
+            Ok::<_, std::io::Error>(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    /// Checks the first `total` bytes against the expected checksum, if
+    /// any, and returns their SHA-256.
+    pub async fn finish(&self, total: u64) -> Result<String, DownloadError> {
+        self.catch_up(total).await?;
+        let hashers = std::mem::replace(&mut self.inner.lock().unwrap().0, self.hashers());
+        let mut digests = hashers.into_iter().map(Hasher::finalize);
+        let sha256 = digests.next().unwrap_or_default();
+        if let Some(checksum) = &self.checksum {
+            checksum.check(digests.next().unwrap_or_else(|| sha256.clone()))?;
+        }
+        Ok(sha256)
+    }
+}
+
+pub enum Hasher {
+    Sha256(sha2::Sha256),
+    Md5(md5::Md5),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    pub fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Sha256 => Self::Sha256(sha2::Sha256::new()),
+            Algorithm::Md5 => Self::Md5(md5::Md5::new()),
+            Algorithm::Blake3 => Self::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(h) => h.update(data),
+            Self::Md5(h) => h.update(data),
+            Self::Blake3(h) => {
+                h.update(data);
+            }
+        }
+    }
+
+    pub fn finalize(self) -> String {
+        let bytes = match self {
+            Self::Sha256(h) => h.finalize().to_vec(),
+            Self::Md5(h) => h.finalize().to_vec(),
+            Self::Blake3(h) => h.finalize().as_bytes().to_vec(),
+        };
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
@@ -0,0 +1,65 @@
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChunkState {
+    Pending,
+    Downloading { bytes: u64 },
+    Done,
+    Failed { error: String, retries: usize },
+}
+
+/// Live per-chunk view of a download, kept alongside the journal for UIs.
+#[derive(Default)]
+pub(super) struct ChunkMap(Mutex<Vec<(ChunkState, usize)>>);
+
+impl ChunkMap {
+    fn inner(&self) -> MutexGuard<'_, Vec<(ChunkState, usize)>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn reset(&self, total: u64, done: impl Fn(u64) -> bool) {
+        *self.inner() = (0..total)
+            .map(|i| match done(i) {
+                true => (ChunkState::Done, 0),
+                false => (ChunkState::Pending, 0),
+            })
+            .collect();
+    }
+
+    fn update(&self, chunk: u64, f: impl FnOnce(&mut (ChunkState, usize))) {
+        if let Some(entry) = self.inner().get_mut(chunk as usize) {
+            f(entry);
+        }
+    }
+
+    pub fn add(&self, chunk: u64, bytes: u64) {
+        self.update(chunk, |(state, _)| match state {
+            ChunkState::Downloading { bytes: b } => *b += bytes,
+            _ => *state = ChunkState::Downloading { bytes },
+        });
+    }
+
+    pub fn retry(&self, chunk: u64) {
+        self.update(chunk, |(_, retries)| *retries += 1);
+    }
+
+    pub fn done(&self, chunk: u64) {
+        self.update(chunk, |(state, _)| *state = ChunkState::Done);
+    }
+
+    pub fn fail(&self, chunk: u64, error: String) {
+        self.update(chunk, |(state, retries)| {
+            *state = ChunkState::Failed {
+                error,
+                retries: *retries,
+            }
+        });
+    }
+
+    pub fn snapshot(&self) -> Vec<ChunkState> {
+        self.inner()
+            .iter()
+            .map(|(state, _)| state.clone())
+            .collect()
+    }
+}
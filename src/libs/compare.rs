@@ -0,0 +1,107 @@
+use super::{Algorithm, Checksum, DownloadError, Downloader, state::SAMPLE};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use std::{
+    fmt,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+const SAMPLED_RANGES: u64 = 8;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Identical(String),
+    Different(String),
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Identical(reason) => write!(f, "identical ({})", reason),
+            Self::Different(reason) => write!(f, "different ({})", reason),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl Downloader {
+    /// Digest the server advertised for the whole file, if any.
+    fn server_checksum(&self) -> Option<Checksum> {
+        let header = |name: &str| self.headers.get(name)?.to_str().ok();
+        let fields = header("repr-digest")
+            .into_iter()
+            .chain(header("digest"))
+            .flat_map(|v| v.split(','));
+        for field in fields {
+            let Some((name, value)) = field.split_once('=') else {
+                continue;
+            };
+            let algorithm = match name.trim().to_ascii_lowercase().as_str() {
+                "sha-256" => Algorithm::Sha256,
+                "md5" => Algorithm::Md5,
+                _ => continue,
+            };
+            if let Ok(digest) = STANDARD.decode(value.trim().trim_matches(':')) {
+                return Some(Checksum::new(algorithm, &hex(&digest)));
+            }
+        }
+        if let Some(md5) = header("content-md5").and_then(|v| STANDARD.decode(v.trim()).ok()) {
+            return Some(Checksum::new(Algorithm::Md5, &hex(&md5)));
+        }
+        // Single-part S3-style ETags are the MD5 of the body
+        let etag = header("etag")?.trim_matches('"');
+        (etag.len() == 32 && etag.bytes().all(|b| b.is_ascii_hexdigit()))
+            .then(|| Checksum::new(Algorithm::Md5, etag))
+    }
+
+    /// Checks whether `path` already holds this download, without fetching
+    /// more than a few sampled ranges.
+    pub async fn compare(&self, path: impl AsRef<Path>) -> Result<Comparison, DownloadError> {
+        let path = path.as_ref();
+        let total = self.state.total_size;
+        let local = std::fs::metadata(path)?.len();
+        if total > 0 && local != total {
+            return Ok(Comparison::Different(format!(
+                "{} bytes here, {} remote",
+                local, total
+            )));
+        }
+        if let Some(checksum) = self.server_checksum() {
+            let algorithm = format!("{:?}", checksum.algorithm).to_ascii_lowercase();
+            return match checksum.verify(path).await {
+                Ok(()) => Ok(Comparison::Identical(format!("{} matches", algorithm))),
+                Err(DownloadError::ChecksumMismatch { .. }) => {
+                    Ok(Comparison::Different(format!("{} differs", algorithm)))
+                }
+                Err(e) => Err(e),
+            };
+        }
+        if total == 0 {
+            return Ok(Comparison::Different("remote size is unknown".to_owned()));
+        }
+        let mut file = File::open(path)?;
+        let samples = SAMPLED_RANGES.min(total.div_ceil(SAMPLE as u64));
+        for k in 0..samples {
+            let start = (total - SAMPLE.min(total as usize) as u64) * k / (samples - 1).max(1);
+            let len = (SAMPLE as u64).min(total - start);
+            let remote = self.read_range(start, len).await?;
+            let mut ours = vec![0; len as usize];
+            file.seek(SeekFrom::Start(start))?;
+            file.read_exact(&mut ours)?;
+            if remote != ours {
+                return Ok(Comparison::Different(format!(
+                    "bytes at offset {} differ",
+                    start
+                )));
+            }
+        }
+        Ok(Comparison::Identical(format!(
+            "size and {} sampled ranges match",
+            samples
+        )))
+    }
+}
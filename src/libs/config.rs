@@ -0,0 +1,861 @@
+use super::{
+    Checksum, Policy, Quota, Sandbox, SpeedTest, Vars, consts::*, gate::Gate, policy::matches_any,
+    safe_join, state::fingerprint,
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use encoding_rs::Encoding;
+use reqwest::{
+    Client, ClientBuilder, NoProxy, Proxy, Url, Version,
+    cookie::Jar,
+    header::{ACCEPT_ENCODING, AUTHORIZATION, HeaderMap, HeaderName, HeaderValue},
+    redirect,
+};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+#[derive(Clone, Debug)]
+pub enum ArtifactDir {
+    Alongside,
+    Subfolder(String),
+    Temp,
+}
+
+#[derive(Clone, Debug)]
+pub struct Artifacts {
+    pub dir: ArtifactDir,
+    pub pattern: String,
+}
+
+impl Default for Artifacts {
+    fn default() -> Self {
+        Self {
+            dir: ArtifactDir::Alongside,
+            pattern: "{name}.{ext}".to_owned(),
+        }
+    }
+}
+
+impl Artifacts {
+    /// Whether one folder holds the artifacts of outputs from every folder.
+    fn shared(&self) -> bool {
+        match &self.dir {
+            ArtifactDir::Alongside => false,
+            ArtifactDir::Subfolder(dir) => Path::new(dir).is_absolute(),
+            ArtifactDir::Temp => true,
+        }
+    }
+
+    /// The pattern, with `.{ext}` added when it lacks one so the part,
+    /// state and output files never share a path, and `{hash}-` when the
+    /// folder is shared so two outputs with one name never do either.
+    fn pattern(&self) -> String {
+        let mut pattern = self.pattern.clone();
+        if !pattern.contains("{ext}") {
+            pattern.push_str(".{ext}");
+        }
+        match self.shared() && !pattern.contains("{hash}") {
+            true => format!("{{hash}}-{}", pattern),
+            false => pattern,
+        }
+    }
+
+    pub fn path_for(&self, output: &str, ext: &str) -> PathBuf {
+        let output = Path::new(output);
+        let name = output
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let hash = std::path::absolute(output)
+            .map(|p| fingerprint(p.as_os_str().as_encoded_bytes()))
+            .unwrap_or_default();
+        let file = Vars::new()
+            .with("name", name)
+            .with("ext", ext)
+            .with("hash", format!("{:016x}", hash))
+            .render(&self.pattern());
+        let parent = output.parent().unwrap_or(Path::new(""));
+        match &self.dir {
+            ArtifactDir::Alongside => parent.join(file),
+            ArtifactDir::Subfolder(dir) => parent.join(dir).join(file),
+            ArtifactDir::Temp => std::env::temp_dir().join(file),
+        }
+    }
+
+    /// The output whose `ext` artifact is `path`, if `path` is one.
+    /// Artifacts in a shared folder don't say which folder their output is
+    /// in.
+    pub fn output_for(&self, path: &Path, ext: &str) -> Option<PathBuf> {
+        if self.shared() {
+            return None;
+        }
+        let file = path.file_name()?.to_str()?;
+        let parent = path.parent()?;
+        let dir = match &self.dir {
+            ArtifactDir::Alongside => parent,
+            ArtifactDir::Subfolder(sub) if parent.ends_with(sub) => parent
+                .ancestors()
+                .nth(Path::new(sub).components().count())?,
+            _ => return None,
+        };
+        let pattern = self.pattern();
+        let (before, after) = pattern.split_once("{name}")?;
+        // Hash digits are matched loosely here and checked by rendering below
+        let render = |t: &str| {
+            Vars::new()
+                .with("ext", ext)
+                .with("hash", "\0".repeat(16))
+                .render(t)
+        };
+        let fits = |template: &str, text: &str| {
+            template.len() == text.len()
+                && template
+                    .bytes()
+                    .zip(text.bytes())
+                    .all(|(t, c)| t == c || t == 0 && c.is_ascii_hexdigit())
+        };
+        let (before, after) = (render(before), render(after));
+        let end = file.len().checked_sub(after.len())?;
+        let name = file.get(before.len()..end).filter(|n| !n.is_empty())?;
+        if !fits(&before, &file[..before.len()]) || !fits(&after, &file[end..]) {
+            return None;
+        }
+        let output = dir.join(name);
+        (self.path_for(&output.to_string_lossy(), ext) == path).then_some(output)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Auth {
+    Basic {
+        user: String,
+        password: Option<String>,
+    },
+    Bearer(String),
+}
+
+impl Auth {
+    fn header(&self) -> Option<HeaderValue> {
+        let value = match self {
+            Self::Basic { user, password } => format!(
+                "Basic {}",
+                STANDARD.encode(format!(
+                    "{}:{}",
+                    user,
+                    password.as_deref().unwrap_or_default()
+                ))
+            ),
+            Self::Bearer(token) => format!("Bearer {}", token),
+        };
+        let mut value = HeaderValue::from_str(&value).ok()?;
+        value.set_sensitive(true);
+        Some(value)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ProxyConfig {
+    pub url: String,
+    pub credentials: Option<(String, String)>,
+    pub no_proxy: Vec<String>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub enum ProxyMode {
+    #[default]
+    System,
+    Direct,
+    Manual(ProxyConfig),
+}
+
+impl ProxyMode {
+    pub fn from_env() -> Self {
+        match std::env::var("STUPIDL_PROXY").as_deref() {
+            Ok("") | Err(_) => Self::System,
+            Ok("none" | "direct") => Self::Direct,
+            Ok(url) => Self::Manual(ProxyConfig {
+                url: url.to_owned(),
+                credentials: None,
+                no_proxy: std::env::var("STUPIDL_NO_PROXY")
+                    .map(|v| v.split(',').map(|h| h.trim().to_owned()).collect())
+                    .unwrap_or_default(),
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Collision {
+    #[default]
+    Rename,
+    Overwrite,
+    Skip,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnFailure {
+    #[default]
+    Keep,
+    Delete,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    /// HTTP/2 when the server offers it over TLS ALPN, HTTP/1.1 otherwise.
+    #[default]
+    Auto,
+    Http1,
+    /// HTTP/2 without negotiating, so ranges share one multiplexed
+    /// connection even over plain `http://`. Servers that only speak
+    /// HTTP/1.1 fail.
+    Http2,
+    #[cfg(feature = "http3")]
+    Http3,
+}
+
+impl Protocol {
+    pub fn of(version: Version) -> Self {
+        match version {
+            Version::HTTP_2 => Self::Http2,
+            #[cfg(feature = "http3")]
+            Version::HTTP_3 => Self::Http3,
+            _ => Self::Http1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChunkOrder {
+    #[default]
+    Sequential,
+    Random,
+    HeadFirst,
+}
+
+impl ChunkOrder {
+    pub fn arrange(&self, mut chunks: Vec<u64>) -> Vec<u64> {
+        match self {
+            Self::Sequential => {}
+            Self::Random => {
+                let mut seed = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map_or(1, |d| d.as_nanos() as u64)
+                    | 1;
+                for i in (1..chunks.len()).rev() {
+                    seed ^= seed << 13;
+                    seed ^= seed >> 7;
+                    seed ^= seed << 17;
+                    chunks.swap(i, (seed % (i as u64 + 1)) as usize);
+                }
+            }
+            Self::HeadFirst => {
+                if chunks.len() > 2 {
+                    let last = chunks.pop().unwrap_or_default();
+                    chunks.insert(1, last);
+                }
+            }
+        }
+        chunks
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ChunkStrategy {
+    Fixed(u64),
+    Adaptive,
+}
+
+impl ChunkStrategy {
+    pub fn chunk_size(&self, total_size: u64) -> u64 {
+        match *self {
+            Self::Fixed(size) => size.min(total_size.max(1)),
+            Self::Adaptive => SIZE_TABLE
+                .iter()
+                .find(|(limit, _)| total_size <= *limit)
+                .map_or(MB, |&(_, size)| size)
+                .max(total_size.div_ceil(MAX_CHUNKS)),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HostOverride {
+    pub pattern: String,
+    pub max_parallel: Option<usize>,
+    pub chunk_size: Option<u64>,
+    pub parallel: bool,
+}
+
+impl HostOverride {
+    pub fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.to_owned(),
+            max_parallel: None,
+            chunk_size: None,
+            parallel: true,
+        }
+    }
+
+    pub fn max_parallel(mut self, max: usize) -> Self {
+        self.max_parallel = Some(max.max(1));
+        self
+    }
+
+    pub fn chunk_size(mut self, size: u64) -> Self {
+        self.chunk_size = Some(size.max(1));
+        self
+    }
+
+    pub fn sequential(mut self) -> Self {
+        self.parallel = false;
+        self
+    }
+}
+
+fn builtin_hosts() -> Vec<HostOverride> {
+    vec![
+        HostOverride::new("*.githubusercontent.com").max_parallel(8),
+        HostOverride::new("*.sourceforge.net").max_parallel(4),
+        HostOverride::new("drive.usercontent.google.com").max_parallel(4),
+        HostOverride::new("*.mediafire.com").sequential(),
+    ]
+}
+
+#[derive(Clone, Debug)]
+pub struct DownloaderConfig {
+    pub strategy: ChunkStrategy,
+    pub order: ChunkOrder,
+    pub max_parallel: usize,
+    pub host_limit: Option<usize>,
+    pub auto_tune: bool,
+    pub protocol: Protocol,
+    pub protocol_limits: HashMap<Protocol, usize>,
+    pub connect_timeout: Option<Duration>,
+    pub read_timeout: Option<Duration>,
+    pub stall_timeout: Option<Duration>,
+    pub retries: usize,
+    pub user_agent: String,
+    pub charset: Option<&'static Encoding>,
+    pub headers: HeaderMap,
+    pub auth: Option<Auth>,
+    pub cookies: Arc<Jar>,
+    pub proxy: ProxyMode,
+    pub artifacts: Artifacts,
+    pub rate_limit: u64,
+    pub connection_rate: u64,
+    pub no_index: bool,
+    pub zone_identifier: bool,
+    pub compressed: bool,
+    pub mmap: bool,
+    pub trace: bool,
+    pub hosts: Vec<HostOverride>,
+    pub checksum: Option<Checksum>,
+    pub collision: Collision,
+    pub output_dir: Option<PathBuf>,
+    pub filename: Option<String>,
+    pub on_failure: OnFailure,
+    pub partial: bool,
+    pub quotas: Vec<Quota>,
+    pub max_redirects: usize,
+    pub same_host_redirects: bool,
+    pub shared_client: Option<Client>,
+    /// Connection ceiling shared by every download made from clones of
+    /// this config; set by `DownloadManager`.
+    pub(crate) connections: Option<Arc<Gate>>,
+    pub policy: Option<Policy>,
+    pub sandbox: Option<Sandbox>,
+}
+
+impl Default for DownloaderConfig {
+    fn default() -> Self {
+        Self {
+            strategy: ChunkStrategy::Adaptive,
+            order: ChunkOrder::default(),
+            max_parallel: 32,
+            host_limit: None,
+            auto_tune: false,
+            protocol: Protocol::default(),
+            protocol_limits: HashMap::new(),
+            connect_timeout: None,
+            read_timeout: None,
+            stall_timeout: Some(Duration::from_secs(30)),
+            retries: 3,
+            user_agent: UA.to_owned(),
+            charset: None,
+            headers: HeaderMap::new(),
+            auth: None,
+            cookies: Arc::new(Jar::default()),
+            proxy: ProxyMode::default(),
+            artifacts: Artifacts::default(),
+            rate_limit: 0,
+            connection_rate: 0,
+            no_index: true,
+            zone_identifier: cfg!(windows),
+            compressed: false,
+            mmap: false,
+            trace: false,
+            hosts: builtin_hosts(),
+            checksum: None,
+            collision: Collision::default(),
+            output_dir: None,
+            filename: None,
+            on_failure: OnFailure::default(),
+            partial: false,
+            quotas: Vec::new(),
+            max_redirects: 10,
+            same_host_redirects: false,
+            shared_client: None,
+            connections: None,
+            policy: None,
+            sandbox: None,
+        }
+    }
+}
+
+impl DownloaderConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn chunk_size(mut self, size: u64) -> Self {
+        self.strategy = ChunkStrategy::Fixed(size.max(1));
+        self
+    }
+
+    pub fn strategy(mut self, strategy: ChunkStrategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn order(mut self, order: ChunkOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn max_parallel(mut self, max: usize) -> Self {
+        self.max_parallel = max.clamp(1, MAX_PARALLEL);
+        self
+    }
+
+    /// Caps concurrent requests to any one host when mirrors spread a
+    /// download over several.
+    pub fn host_limit(mut self, max: usize) -> Self {
+        self.host_limit = Some(max.max(1));
+        self
+    }
+
+    /// Drops connections that add no speed, and starts later downloads
+    /// from the same host at the count that was enough. Off by default.
+    pub fn auto_tune(mut self, enabled: bool) -> Self {
+        self.auto_tune = enabled;
+        self
+    }
+
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    pub fn protocol_limit(mut self, protocol: Protocol, max: usize) -> Self {
+        self.protocol_limits.insert(protocol, max.max(1));
+        self
+    }
+
+    pub fn for_version(&self, version: Version) -> Self {
+        let mut config = self.clone();
+        if let Some(&max) = self.protocol_limits.get(&Protocol::of(version)) {
+            config.max_parallel = config.max_parallel.min(max);
+        }
+        config
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    pub fn stall_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.stall_timeout = timeout;
+        self
+    }
+
+    /// How many times a failed download goes back for its missing chunks
+    /// before the error is reported.
+    pub fn retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn user_agent(mut self, ua: &str) -> Self {
+        self.user_agent = ua.to_owned();
+        self
+    }
+
+    pub fn charset(mut self, label: &str) -> Self {
+        self.charset = Encoding::for_label(label.as_bytes());
+        self
+    }
+
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        self.headers.extend(headers);
+        self
+    }
+
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    pub fn basic_auth(mut self, user: &str, password: Option<&str>) -> Self {
+        self.auth = Some(Auth::Basic {
+            user: user.to_owned(),
+            password: password.map(str::to_owned),
+        });
+        self
+    }
+
+    pub fn bearer_auth(mut self, token: &str) -> Self {
+        self.auth = Some(Auth::Bearer(token.to_owned()));
+        self
+    }
+
+    pub fn cookie_jar(mut self, jar: Arc<Jar>) -> Self {
+        self.cookies = jar;
+        self
+    }
+
+    pub fn cookie(self, url: &str, cookies: &str) -> Self {
+        if let Ok(url) = Url::parse(url) {
+            for cookie in cookies.split(';').map(str::trim).filter(|c| !c.is_empty()) {
+                self.cookies.add_cookie_str(cookie, &url);
+            }
+        }
+        self
+    }
+
+    pub fn proxy(mut self, proxy: ProxyMode) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
+    pub fn artifacts(mut self, dir: ArtifactDir, pattern: &str) -> Self {
+        self.artifacts = Artifacts {
+            dir,
+            pattern: pattern.to_owned(),
+        };
+        self
+    }
+
+    pub fn rate_limit(mut self, bytes_per_sec: u64) -> Self {
+        self.rate_limit = bytes_per_sec;
+        self
+    }
+
+    /// Caps each chunk connection on its own, for servers that ban clients
+    /// pulling too fast over a single stream. 0 means unlimited.
+    pub fn connection_rate(mut self, bytes_per_sec: u64) -> Self {
+        self.connection_rate = bytes_per_sec;
+        self
+    }
+
+    pub fn no_index(mut self, no_index: bool) -> Self {
+        self.no_index = no_index;
+        self
+    }
+
+    /// Tags finished files with the Mark-of-the-Web, as browsers do, so
+    /// SmartScreen and Office treat them as coming from the internet.
+    pub fn zone_identifier(mut self, enabled: bool) -> Self {
+        self.zone_identifier = enabled;
+        self
+    }
+
+    /// Fetches the file in a single compressed stream instead of ranged
+    /// chunks, letting the server gzip or brotli it on the fly.
+    pub fn compressed(mut self, compressed: bool) -> Self {
+        self.compressed = compressed;
+        self
+    }
+
+    /// Writes through a memory map of the part file instead of positioned
+    /// writes; falls back to the latter when the file can't be mapped.
+    pub fn mmap(mut self, mmap: bool) -> Self {
+        self.mmap = mmap;
+        self
+    }
+
+    pub fn trace(mut self, trace: bool) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    pub fn checksum(mut self, checksum: Checksum) -> Self {
+        self.checksum = Some(checksum);
+        self
+    }
+
+    pub fn collision(mut self, collision: Collision) -> Self {
+        self.collision = collision;
+        self
+    }
+
+    pub fn output_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.output_dir = Some(dir.into());
+        self
+    }
+
+    pub fn filename(mut self, name: &str) -> Self {
+        self.filename = Some(name.to_owned());
+        self
+    }
+
+    /// The explicit filename, or `inferred`, under the output directory
+    /// when one is set.
+    pub fn destination(&self, inferred: &str) -> String {
+        let name = self.filename.as_deref().unwrap_or(inferred);
+        let dir = self.output_dir.as_deref().unwrap_or(Path::new(""));
+        safe_join(dir, name).to_string_lossy().into_owned()
+    }
+
+    /// Lets `join` report chunks that never arrived as missing ranges
+    /// instead of failing, keeping the part and state files for later.
+    pub fn allow_partial(mut self, partial: bool) -> Self {
+        self.partial = partial;
+        self
+    }
+
+    pub fn on_failure(mut self, on_failure: OnFailure) -> Self {
+        self.on_failure = on_failure;
+        self
+    }
+
+    pub fn redirects(mut self, max: usize, same_host: bool) -> Self {
+        self.max_redirects = max;
+        self.same_host_redirects = same_host;
+        self
+    }
+
+    /// Caps the total size of everything under `dir`; downloads into it wait
+    /// for room instead of filling it up.
+    pub fn quota(mut self, dir: impl Into<PathBuf>, max: u64) -> Self {
+        self.quotas.push(Quota::new(dir, max));
+        self
+    }
+
+    pub fn quota_for(&self, output: &str) -> Option<Quota> {
+        self.quotas
+            .iter()
+            .filter(|q| q.covers(output.as_ref()))
+            .max_by_key(|q| q.dir.components().count())
+            .cloned()
+    }
+
+    pub fn host_override(mut self, host: HostOverride) -> Self {
+        self.hosts.insert(0, host);
+        self
+    }
+
+    pub fn for_host(&self, host: &str) -> Self {
+        let mut config = self.clone();
+        if let Some(host) = self
+            .hosts
+            .iter()
+            .find(|h| matches_any(std::slice::from_ref(&h.pattern), host))
+        {
+            // Overrides only ever lower what the caller asked for
+            if let Some(max) = host.max_parallel {
+                config.max_parallel = config.max_parallel.min(max);
+            }
+            if let Some(size) = host.chunk_size
+                && matches!(config.strategy, ChunkStrategy::Adaptive)
+            {
+                config.strategy = ChunkStrategy::Fixed(size);
+            }
+            if !host.parallel {
+                config.strategy = ChunkStrategy::Fixed(u64::MAX);
+                config.max_parallel = 1;
+            }
+        }
+        config
+    }
+
+    /// Reuses `client` for every request instead of building one from this
+    /// config, so several downloads share a connection pool.
+    pub fn shared_client(mut self, client: Client) -> Self {
+        self.shared_client = Some(client);
+        self
+    }
+
+    /// Host and file type rules, checked on the final URL before downloading.
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Keeps every output inside the sandbox's directories.
+    pub fn sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// Starts downloads at the connection count `test` found optimal;
+    /// auto-tuning still lowers it per host.
+    pub fn calibrate(mut self, test: &SpeedTest) -> Self {
+        self.max_parallel = test.optimal().clamp(1, MAX_PARALLEL);
+        self
+    }
+
+    /// The client downloads use: proxy, timeouts, cookies and headers
+    /// applied.
+    pub fn client(&self) -> reqwest::Result<Client> {
+        if let Some(client) = &self.shared_client {
+            return Ok(client.clone());
+        }
+        let (max, same_host) = (self.max_redirects, self.same_host_redirects);
+        self.builder()?
+            .redirect(redirect::Policy::custom(move |attempt| {
+                if attempt.previous().len() > max {
+                    attempt.error("too many redirects")
+                } else if same_host && attempt.url().host_str() != attempt.previous()[0].host_str()
+                {
+                    attempt.error("redirect leaves the original host")
+                } else {
+                    attempt.follow()
+                }
+            }))
+            .build()
+    }
+
+    /// Like `client`, but hands every redirect back to the caller. A shared
+    /// client keeps its own redirect policy.
+    pub fn manual_client(&self) -> reqwest::Result<Client> {
+        match &self.shared_client {
+            Some(client) => Ok(client.clone()),
+            None => self.builder()?.redirect(redirect::Policy::none()).build(),
+        }
+    }
+
+    fn builder(&self) -> reqwest::Result<ClientBuilder> {
+        let mut headers = self.headers.clone();
+        if let Some(auth) = self.auth.as_ref().and_then(Auth::header) {
+            headers.insert(AUTHORIZATION, auth);
+        }
+        if !self.compressed {
+            headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("identity"));
+        }
+        let mut builder = Client::builder()
+            .user_agent(&self.user_agent)
+            .default_headers(headers)
+            .cookie_provider(self.cookies.clone())
+            .gzip(self.compressed)
+            .brotli(self.compressed)
+            .deflate(self.compressed);
+        builder = match &self.proxy {
+            ProxyMode::System => builder,
+            ProxyMode::Direct => builder.no_proxy(),
+            ProxyMode::Manual(config) => {
+                let mut proxy = Proxy::all(&config.url)?
+                    .no_proxy(NoProxy::from_string(&config.no_proxy.join(",")));
+                if let Some((user, password)) = &config.credentials {
+                    proxy = proxy.basic_auth(user, password);
+                }
+                builder.proxy(proxy)
+            }
+        };
+        builder = match self.protocol {
+            Protocol::Auto => builder,
+            Protocol::Http1 => builder.http1_only(),
+            Protocol::Http2 => builder.http2_prior_knowledge().http2_adaptive_window(true),
+            #[cfg(feature = "http3")]
+            Protocol::Http3 => builder.http3_prior_knowledge(),
+        };
+        if let Some(timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.read_timeout {
+            builder = builder.read_timeout(timeout);
+        }
+        Ok(builder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn host_overrides_never_raise_max_parallel() {
+        let host = "objects.githubusercontent.com";
+        assert_eq!(DownloaderConfig::new().for_host(host).max_parallel, 8);
+        let config = DownloaderConfig::new().max_parallel(2).for_host(host);
+        assert_eq!(config.max_parallel, 2);
+        let config = DownloaderConfig::new().for_host("www.mediafire.com");
+        assert_eq!(config.max_parallel, 1);
+    }
+
+    #[test]
+    fn host_overrides_keep_an_explicit_chunk_size() {
+        let host = HostOverride::new("example.com").chunk_size(MB);
+        let config = DownloaderConfig::new().host_override(host.clone());
+        assert!(matches!(
+            config.for_host("example.com").strategy,
+            ChunkStrategy::Fixed(MB)
+        ));
+        let config = DownloaderConfig::new()
+            .chunk_size(4 * MB)
+            .host_override(host);
+        assert!(matches!(
+            config.for_host("example.com").strategy,
+            ChunkStrategy::Fixed(size) if size == 4 * MB
+        ));
+    }
+
+    #[test]
+    fn artifact_patterns_always_carry_the_extension() {
+        let artifacts = Artifacts {
+            dir: ArtifactDir::Subfolder(".partial".to_owned()),
+            pattern: "{hash}-{name}".to_owned(),
+        };
+        let output = Path::new("downloads").join("a.iso");
+        let part = artifacts.path_for(&output.to_string_lossy(), "part");
+        let state = artifacts.path_for(&output.to_string_lossy(), "stdl");
+        assert_ne!(part, state);
+        assert_ne!(part, output);
+        assert!(part.to_string_lossy().ends_with("-a.iso.part"));
+        assert_eq!(artifacts.output_for(&part, "part"), Some(output.clone()));
+        assert_eq!(artifacts.output_for(&state, "part"), None);
+        let alongside = Artifacts::default();
+        let part = alongside.path_for(&output.to_string_lossy(), "part");
+        assert_eq!(alongside.output_for(&part, "part"), Some(output));
+    }
+
+    #[test]
+    fn shared_artifact_folders_keep_same_named_outputs_apart() {
+        let artifacts = Artifacts {
+            dir: ArtifactDir::Temp,
+            ..Default::default()
+        };
+        let first = Path::new("a").join("setup.exe");
+        let second = Path::new("b").join("setup.exe");
+        for ext in ["part", "stdl"] {
+            let one = artifacts.path_for(&first.to_string_lossy(), ext);
+            let other = artifacts.path_for(&second.to_string_lossy(), ext);
+            assert_ne!(one, other);
+            assert_eq!(one.parent(), Some(std::env::temp_dir().as_path()));
+            assert!(
+                one.to_string_lossy()
+                    .ends_with(&format!("-setup.exe.{}", ext))
+            );
+        }
+        let part = artifacts.path_for(&first.to_string_lossy(), "part");
+        assert_eq!(artifacts.output_for(&part, "part"), None);
+    }
+}
@@ -1,4 +1,23 @@
+use std::time::Duration;
+
 pub const UA: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/135.0.0.0 Safari/537.36 Edg/135.0.0.0";
 
 pub const KB: u64 = 1024;
 pub const MB: u64 = 1024 * KB;
+pub const GB: u64 = 1024 * MB;
+
+pub const MAX_CHUNKS: u64 = 1024;
+pub const MAX_PARALLEL: usize = 256;
+pub const SIZE_TABLE: [(u64, u64); 4] = [
+    (16 * MB, 256 * KB),
+    (256 * MB, MB),
+    (4 * GB, 4 * MB),
+    (u64::MAX, 16 * MB),
+];
+
+pub const STALL_RETRIES: usize = 3;
+pub const RETRY_BACKOFF: Duration = Duration::from_secs(2);
+pub const CANCEL_GRACE: Duration = Duration::from_secs(5);
+pub const MIN_STEAL: u64 = MB;
+pub const SMALL_FILE: u64 = 256 * KB;
+pub const OVERLAP: usize = 4 * KB as usize;
@@ -0,0 +1,49 @@
+use super::DownloadError;
+use reqwest::{Url, cookie::Jar};
+use std::{path::Path, sync::Arc};
+
+pub fn load_cookies(path: impl AsRef<Path>) -> Result<Arc<Jar>, DownloadError> {
+    let jar = Jar::default();
+    for line in std::fs::read_to_string(path)?.lines() {
+        let line = line.trim();
+        let (line, http_only) = match line.strip_prefix("#HttpOnly_") {
+            Some(line) => (line, true),
+            None if line.is_empty() || line.starts_with('#') => continue,
+            None => (line, false),
+        };
+        let fields: Vec<_> = line.split('\t').collect();
+        let [domain, _, path, secure, expires, name, value] = fields[..] else {
+            continue;
+        };
+        let host = domain.trim_start_matches('.');
+        let secure = secure.eq_ignore_ascii_case("TRUE");
+        let scheme = if secure { "https" } else { "http" };
+        let Ok(url) = Url::parse(&format!("{}://{}{}", scheme, host, path)) else {
+            continue;
+        };
+        let mut cookie = format!("{}={}; Path={}", name, value, path);
+        if domain.starts_with('.') {
+            cookie.push_str(&format!("; Domain={}", host));
+        }
+        if let Ok(expires) = expires.parse::<i64>()
+            && expires > 0
+        {
+            let max_age = expires - now();
+            cookie.push_str(&format!("; Max-Age={}", max_age.max(0)));
+        }
+        if secure {
+            cookie.push_str("; Secure");
+        }
+        if http_only {
+            cookie.push_str("; HttpOnly");
+        }
+        jar.add_cookie_str(&cookie, &url);
+    }
+    Ok(Arc::new(jar))
+}
+
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
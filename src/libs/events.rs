@@ -0,0 +1,163 @@
+use super::{DownloadError, Progress, ProgressSink};
+use serde::Serialize;
+use std::{
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+const SPEED_SAMPLE: Duration = Duration::from_secs(1);
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Probe {
+        url: String,
+        final_url: String,
+        status: u16,
+        total_size: u64,
+        chunk_size: u64,
+        total_chunk: u64,
+        etag: Option<String>,
+        last_modified: Option<String>,
+    },
+    Start {
+        downloaded: u64,
+    },
+    Speed {
+        downloaded: u64,
+        speed: f64,
+    },
+    ChunkRetry {
+        chunk: u64,
+    },
+    ChunkComplete {
+        chunk: u64,
+    },
+    MirrorFailed {
+        mirror: String,
+        error: String,
+    },
+    WaitingForSpace {
+        dir: String,
+        needed: u64,
+        available: u64,
+    },
+    Error {
+        error: String,
+    },
+    Finish {
+        downloaded: u64,
+    },
+}
+
+#[derive(Serialize)]
+struct Entry {
+    at_ms: u128,
+    #[serde(flatten)]
+    event: Event,
+}
+
+/// Everything that happened to one download, for support reports.
+pub(super) struct EventLog {
+    since: Instant,
+    entries: Mutex<(Vec<Entry>, Option<Instant>)>,
+}
+
+impl EventLog {
+    pub fn new() -> Self {
+        Self {
+            since: Instant::now(),
+            entries: Mutex::new((Vec::new(), None)),
+        }
+    }
+
+    fn entries(&self) -> MutexGuard<'_, (Vec<Entry>, Option<Instant>)> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn push(&self, event: Event) {
+        let at_ms = self.since.elapsed().as_millis();
+        self.entries().0.push(Entry { at_ms, event });
+    }
+
+    fn sample(&self, progress: &Progress) {
+        let mut entries = self.entries();
+        if entries.1.is_some_and(|last| last.elapsed() < SPEED_SAMPLE) {
+            return;
+        }
+        entries.1 = Some(Instant::now());
+        entries.0.push(Entry {
+            at_ms: self.since.elapsed().as_millis(),
+            event: Event::Speed {
+                downloaded: progress.downloaded,
+                speed: progress.speed,
+            },
+        });
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&self.entries().0).unwrap_or_default()
+    }
+}
+
+/// Sink that records into the event log before handing on to the caller's.
+pub(super) struct Recorder {
+    pub log: Arc<EventLog>,
+    pub inner: Arc<dyn ProgressSink>,
+}
+
+impl ProgressSink for Recorder {
+    fn on_start(&self, progress: &Progress) {
+        self.log.push(Event::Start {
+            downloaded: progress.downloaded,
+        });
+        self.inner.on_start(progress);
+    }
+
+    fn on_chunk_complete(&self, chunk: u64) {
+        self.log.push(Event::ChunkComplete { chunk });
+        self.inner.on_chunk_complete(chunk);
+    }
+
+    fn on_chunk_retry(&self, chunk: u64) {
+        self.log.push(Event::ChunkRetry { chunk });
+        self.inner.on_chunk_retry(chunk);
+    }
+
+    fn on_progress(&self, progress: &Progress) {
+        self.log.sample(progress);
+        self.inner.on_progress(progress);
+    }
+
+    fn on_mirror_failed(&self, mirror: &str, error: &DownloadError) {
+        self.log.push(Event::MirrorFailed {
+            mirror: mirror.to_owned(),
+            error: error.to_string(),
+        });
+        self.inner.on_mirror_failed(mirror, error);
+    }
+
+    fn on_waiting_for_space(&self, dir: &Path, needed: u64, available: u64) {
+        self.log.push(Event::WaitingForSpace {
+            dir: dir.display().to_string(),
+            needed,
+            available,
+        });
+        self.inner.on_waiting_for_space(dir, needed, available);
+    }
+
+    fn on_error(&self, error: &DownloadError) {
+        self.log.push(Event::Error {
+            error: error.to_string(),
+        });
+        self.inner.on_error(error);
+    }
+
+    fn on_finish(&self, progress: &Progress) {
+        self.log.push(Event::Finish {
+            downloaded: progress.downloaded,
+        });
+        self.inner.on_finish(progress);
+    }
+}
@@ -1,46 +1,215 @@
-use percent_encoding::percent_decode_str;
+use chardetng::EncodingDetector;
+use encoding_rs::Encoding;
+use percent_encoding::percent_decode;
 use reqwest::header::CONTENT_DISPOSITION;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-pub fn filename_from(response: &reqwest::Response) -> String {
-    response
+const RESERVED: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+pub fn filename_from(response: &reqwest::Response, fallback: Option<&'static Encoding>) -> String {
+    let name = response
         .headers()
         .get(CONTENT_DISPOSITION)
-        .and_then(|v| v.to_str().ok())
-        .and_then(parse)
+        .map(|v| decode(v.as_bytes(), fallback))
+        .and_then(|v| parse(&v, fallback))
         .or_else(|| {
             Path::new(response.url().path())
                 .file_name()
                 .and_then(|s| s.to_str())
-                .map(|s| percent_decode_str(s).decode_utf8_lossy().into_owned())
+                .map(|s| decode(&percent_decode(s.as_bytes()).collect::<Vec<_>>(), fallback))
         })
-        .unwrap_or("Download".to_owned())
+        .unwrap_or_default();
+    sanitize(&name)
+}
+
+pub fn decode(bytes: &[u8], fallback: Option<&'static Encoding>) -> String {
+    if let Ok(text) = std::str::from_utf8(bytes) {
+        return text.to_owned();
+    }
+    let encoding = fallback.unwrap_or_else(|| {
+        let mut detector = EncodingDetector::new();
+        detector.feed(bytes, true);
+        detector.guess(None, true)
+    });
+    encoding.decode_without_bom_handling(bytes).0.into_owned()
+}
+
+pub fn sanitize(name: &str) -> String {
+    let name = name
         .chars()
         .map(|c| match c {
             '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
             _ => c,
         })
-        .collect::<String>()
+        .collect::<String>();
+    let name = name
         .trim()
         .trim_start_matches('.')
-        .to_owned()
+        .trim_end_matches(['.', ' '])
+        .to_owned();
+    let stem = name.split('.').next().unwrap_or_default();
+    match name.as_str() {
+        "" => "Download".to_owned(),
+        _ if RESERVED.iter().any(|r| r.eq_ignore_ascii_case(stem.trim())) => format!("_{}", name),
+        _ => name,
+    }
 }
 
-fn parse(header: &str) -> Option<String> {
+/// Joins an untrusted relative path onto `base_dir`, keeping its
+/// subdirectories but never leaving `base_dir`.
+pub fn safe_join(base_dir: impl AsRef<Path>, server_name: &str) -> PathBuf {
+    let parts: Vec<_> = server_name
+        .split(['/', '\\'])
+        .filter(|p| !matches!(p.trim(), "" | "." | ".."))
+        .map(sanitize)
+        .collect();
+    match parts.is_empty() {
+        true => base_dir.as_ref().join(sanitize("")),
+        false => parts
+            .iter()
+            .fold(base_dir.as_ref().to_path_buf(), |p, c| p.join(c)),
+    }
+}
+
+pub fn unique(output: &str) -> String {
+    let path = Path::new(output);
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let (stem, ext) = match name.get(1..).and_then(|n| n.find('.')) {
+        Some(i) => name.split_at(i + 1),
+        None => (name.as_str(), ""),
+    };
+    (1..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, ext)))
+        .find(|p| !p.exists())
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|| output.to_owned())
+}
+
+pub fn glob(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<_>, Vec<_>) = (pattern.chars().collect(), name.chars().collect());
+    let (mut p, mut n, mut star, mut mark) = (0, 0, None, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some(p);
+                mark = n;
+                p += 1;
+            }
+            Some(&c) if c == '?' || c.eq_ignore_ascii_case(&name[n]) => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some(s) => {
+                    p = s + 1;
+                    mark += 1;
+                    n = mark;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn parse(header: &str, fallback: Option<&'static Encoding>) -> Option<String> {
     header.split(';').find_map(|part| {
         let part = part.trim();
-        if part.starts_with("filename*=") {
-            part.splitn(3, '\'')
-                .nth(2)
-                .map(|s| percent_decode_str(s).decode_utf8_lossy().into_owned())
-        } else if part.starts_with("filename=") {
-            Some(
-                percent_decode_str(part[9..].trim_matches(|c| c == '"' || c == ' '))
-                    .decode_utf8_lossy()
-                    .into(),
-            )
+        if let Some(value) = part.strip_prefix("filename*=") {
+            let mut fields = value.splitn(3, '\'');
+            let charset = Encoding::for_label(fields.next()?.trim().as_bytes()).or(fallback);
+            let bytes: Vec<_> = percent_decode(fields.nth(1)?.as_bytes()).collect();
+            Some(match charset {
+                Some(charset) => charset.decode_without_bom_handling(&bytes).0.into_owned(),
+                None => decode(&bytes, None),
+            })
+        } else if let Some(value) = part.strip_prefix("filename=") {
+            let bytes: Vec<_> =
+                percent_decode(value.trim_matches(|c| c == '"' || c == ' ').as_bytes()).collect();
+            Some(decode(&bytes, fallback))
         } else {
             None
         }
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_strips_traversal() {
+        assert_eq!(sanitize(".."), "Download");
+        assert_eq!(sanitize("../../etc/passwd"), "_.._etc_passwd");
+        assert_eq!(sanitize("..\\..\\boot.ini"), "_.._boot.ini");
+    }
+
+    #[test]
+    fn sanitize_flattens_absolute_paths_and_drives() {
+        assert_eq!(sanitize("/etc/passwd"), "_etc_passwd");
+        assert_eq!(sanitize("C:\\Windows\\win.ini"), "C__Windows_win.ini");
+        assert_eq!(sanitize("C:evil.exe"), "C_evil.exe");
+        assert_eq!(sanitize("\\\\server\\share\\x"), "__server_share_x");
+    }
+
+    #[test]
+    fn sanitize_escapes_reserved_names() {
+        assert_eq!(sanitize("CON"), "_CON");
+        assert_eq!(sanitize("nul.txt"), "_nul.txt");
+        assert_eq!(sanitize("COM1.tar.gz"), "_COM1.tar.gz");
+        assert_eq!(sanitize("LPT9 .log"), "_LPT9 .log");
+        assert_eq!(sanitize("CONSOLE.txt"), "CONSOLE.txt");
+    }
+
+    #[test]
+    fn sanitize_trims_trailing_dots_and_spaces() {
+        assert_eq!(sanitize("report.pdf. . "), "report.pdf");
+        assert_eq!(sanitize("  name  "), "name");
+        assert_eq!(sanitize(". ."), "Download");
+        assert_eq!(sanitize(".hidden"), "hidden");
+    }
+
+    #[test]
+    fn sanitize_replaces_nul_and_control_chars() {
+        assert_eq!(sanitize("a\0b.txt"), "a_b.txt");
+        assert_eq!(sanitize("line\nbreak\t.txt"), "line_break_.txt");
+        assert_eq!(sanitize("\u{7f}del"), "_del");
+        assert_eq!(sanitize("bad\u{1b}[31m.txt"), "bad_[31m.txt");
+    }
+
+    #[test]
+    fn safe_join_stays_inside_base() {
+        let base = Path::new("downloads");
+        assert_eq!(safe_join(base, "../../x"), base.join("x"));
+        assert_eq!(
+            safe_join(base, "/etc/passwd"),
+            base.join("etc").join("passwd")
+        );
+        assert_eq!(safe_join(base, "..\\..\\x.exe"), base.join("x.exe"));
+        assert_eq!(safe_join(base, "C:\\x"), base.join("C_").join("x"));
+        assert_eq!(
+            safe_join(base, "sub/./file.bin"),
+            base.join("sub").join("file.bin")
+        );
+        assert_eq!(safe_join(base, ".."), base.join("Download"));
+        assert_eq!(safe_join(base, ""), base.join("Download"));
+        assert_eq!(safe_join(base, "aux"), base.join("_aux"));
+        for name in [
+            "../a",
+            "/a",
+            "a/../../b",
+            "\\\\host\\a",
+            "C:/a",
+            "..\u{0}/a",
+        ] {
+            assert!(safe_join(base, name).starts_with(base), "{}", name);
+        }
+    }
+}
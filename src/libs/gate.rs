@@ -0,0 +1,83 @@
+use std::sync::Mutex;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+/// A concurrency limit that can be raised or lowered while permits are out.
+#[derive(Debug)]
+pub struct Gate {
+    semaphore: Semaphore,
+    // (limit, permits still to be retired once they come back)
+    inner: Mutex<(usize, usize)>,
+}
+
+impl Gate {
+    pub fn new(limit: usize) -> Self {
+        let limit = limit.clamp(1, Semaphore::MAX_PERMITS);
+        Self {
+            semaphore: Semaphore::new(limit),
+            inner: Mutex::new((limit, 0)),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.inner.lock().unwrap().0
+    }
+
+    /// Permits held right now; stays above a lowered limit until the extra
+    /// connections finish their chunks.
+    pub fn active(&self) -> usize {
+        let (limit, debt) = *self.inner.lock().unwrap();
+        (limit + debt).saturating_sub(self.semaphore.available_permits())
+    }
+
+    pub fn set_limit(&self, limit: usize) {
+        let limit = limit.clamp(1, Semaphore::MAX_PERMITS);
+        let mut inner = self.inner.lock().unwrap();
+        let (old, debt) = *inner;
+        if limit >= old {
+            let repaid = debt.min(limit - old);
+            self.semaphore.add_permits(limit - old - repaid);
+            *inner = (limit, debt - repaid);
+        } else {
+            let forgotten = self.semaphore.forget_permits(old - limit);
+            *inner = (limit, debt + old - limit - forgotten);
+        }
+    }
+
+    pub async fn acquire(&self) -> SemaphorePermit<'_> {
+        loop {
+            let permit = self
+                .semaphore
+                .acquire()
+                .await
+                .expect("gate is never closed");
+            let mut inner = self.inner.lock().unwrap();
+            if inner.1 == 0 {
+                return permit;
+            }
+            inner.1 -= 1;
+            permit.forget();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn lowered_limits_drain_as_permits_return() {
+        let gate = Gate::new(4);
+        let mut held = Vec::new();
+        for _ in 0..4 {
+            held.push(gate.acquire().await);
+        }
+        gate.set_limit(2);
+        assert_eq!(gate.active(), 4);
+        held.truncate(2);
+        assert_eq!(gate.active(), 2);
+        held.clear();
+        let _again = (gate.acquire().await, gate.acquire().await);
+        assert_eq!(gate.active(), 2);
+        assert!(gate.semaphore.try_acquire().is_err());
+    }
+}
@@ -0,0 +1,102 @@
+use super::{
+    ChunkState, Downloader, Progress, Status, Tracer, chunks::ChunkMap, consts::MAX_PARALLEL,
+    events::EventLog, gate::Gate, limiter::Limiter,
+};
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+use tokio::sync::watch::{Receiver, Sender};
+
+/// Cheap view of a download for observers that don't own it: progress,
+/// pause/resume and throttling, but no way to move or join the task.
+#[derive(Clone)]
+pub struct DownloadHandle {
+    tracer: Tracer,
+    pause: Sender<bool>,
+    stop: Arc<AtomicBool>,
+    limiter: Arc<Limiter>,
+    gate: Arc<Gate>,
+    chunks: Arc<ChunkMap>,
+    events: Arc<EventLog>,
+}
+
+impl DownloadHandle {
+    pub fn progress(&self) -> Progress {
+        self.tracer.progress()
+    }
+
+    pub fn watcher(&self) -> Receiver<Progress> {
+        self.tracer.sender.subscribe()
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.tracer.speed()
+    }
+
+    pub fn eta(&self) -> Option<Duration> {
+        self.tracer.eta()
+    }
+
+    pub fn pause(&self) {
+        self.pause.send_replace(true);
+        self.tracer.set_status(Status::Paused);
+    }
+
+    pub fn resume(&self) {
+        self.pause.send_replace(false);
+        self.tracer.set_status(Status::Running);
+    }
+
+    pub fn paused(&self) -> bool {
+        *self.pause.borrow()
+    }
+
+    /// Asks the download to wind down and save its journal; the owner's
+    /// `join` returns once it has.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+        self.pause.send_replace(false);
+    }
+
+    pub fn rate_limit(&self) -> u64 {
+        self.limiter.rate()
+    }
+
+    pub fn set_rate_limit(&self, bytes_per_sec: u64) {
+        self.limiter.set_rate(bytes_per_sec);
+    }
+
+    pub fn max_parallel(&self) -> usize {
+        self.gate.limit()
+    }
+
+    pub fn set_max_parallel(&self, max: usize) {
+        self.gate.set_limit(max.min(MAX_PARALLEL));
+    }
+
+    pub fn chunks(&self) -> Vec<ChunkState> {
+        self.chunks.snapshot()
+    }
+
+    pub fn diagnostics(&self) -> String {
+        self.events.to_json()
+    }
+}
+
+impl Downloader {
+    pub fn handle(&self) -> DownloadHandle {
+        DownloadHandle {
+            tracer: self.tracer.clone(),
+            pause: self.pause.clone(),
+            stop: self.stop.clone(),
+            limiter: self.limiter.clone(),
+            gate: self.gate.clone(),
+            chunks: self.chunks.clone(),
+            events: self.events.clone(),
+        }
+    }
+}
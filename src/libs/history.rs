@@ -0,0 +1,83 @@
+use super::{Category, DownloadError};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// One ended download, stored as a line of JSON so appending never rewrites
+/// earlier entries.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    pub url: String,
+    pub path: String,
+    pub category: Category,
+    pub size: u64,
+    pub status: String,
+    /// Seconds since the Unix epoch.
+    pub ended: u64,
+}
+
+impl Record {
+    pub fn new(url: &str, path: &str, category: Category, size: u64, status: &str) -> Self {
+        Self {
+            url: url.to_owned(),
+            path: path.to_owned(),
+            category,
+            size,
+            status: status.to_owned(),
+            ended: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+        }
+    }
+
+    pub fn append(&self, path: impl AsRef<Path>) -> Result<(), DownloadError> {
+        let mut line = serde_json::to_vec(self).map_err(std::io::Error::from)?;
+        line.push(b'\n');
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(file.write_all(&line)?)
+    }
+
+    /// Oldest first. Lines that don't parse, such as one cut short by a
+    /// crash, are skipped rather than hiding the rest.
+    pub fn load(path: impl AsRef<Path>) -> Result<Vec<Self>, DownloadError> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(text
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncated_lines_are_skipped() {
+        let path = std::env::temp_dir().join(format!("stupidl-history-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let movie = Record::new("https://a/m.mkv", "m.mkv", Category::Video, 7, "Completed");
+        let notes = Record::new("https://a/n", "n", Category::Document, 3, "Completed");
+        movie.append(&path).unwrap();
+        notes.append(&path).unwrap();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"url\":").unwrap();
+
+        assert_eq!(Record::load(&path).unwrap(), [movie, notes]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn missing_file_is_empty() {
+        let path = std::env::temp_dir().join("stupidl-history-missing");
+        assert!(Record::load(path).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,98 @@
+use super::{DownloadError, Resolved};
+use reqwest::{Client, Url, header::LOCATION};
+use std::collections::HashMap;
+
+#[derive(Clone, Debug)]
+pub struct HubFile {
+    pub repo: String,
+    pub revision: String,
+    pub path: String,
+    pub token: Option<String>,
+}
+
+impl HubFile {
+    pub fn parse(spec: &str) -> Option<Self> {
+        let rest = spec.strip_prefix("hf://")?;
+        let (kind, rest) = match rest.split_once('/')? {
+            (kind @ ("datasets" | "spaces"), rest) => (Some(kind), rest),
+            _ => (None, rest),
+        };
+        let mut parts = rest.splitn(3, '/');
+        let (org, model, path) = (parts.next()?, parts.next()?, parts.next()?);
+        let (model, revision) = model.split_once('@').unwrap_or((model, "main"));
+        if org.is_empty() || model.is_empty() || path.is_empty() {
+            return None;
+        }
+        let repo = match kind {
+            Some(kind) => format!("{}/{}/{}", kind, org, model),
+            None => format!("{}/{}", org, model),
+        };
+        let token = std::env::var("STUPIDL_HF_TOKEN")
+            .or_else(|_| std::env::var("HF_TOKEN"))
+            .ok()
+            .filter(|t| !t.is_empty());
+        Some(Self {
+            repo,
+            revision: revision.to_owned(),
+            path: path.to_owned(),
+            token,
+        })
+    }
+
+    pub fn url(&self) -> String {
+        format!(
+            "https://huggingface.co/{}/resolve/{}/{}",
+            self.repo, self.revision, self.path
+        )
+    }
+
+    /// Follows the hub's redirects over `client`, normally
+    /// `DownloaderConfig::manual_client`, since the first one carries the
+    /// checksum. A client that follows them itself just finds none.
+    pub async fn resolve(&self, client: &Client) -> Result<Resolved, DownloadError> {
+        let mut url = Url::parse(&self.url()).map_err(|e| DownloadError::Hub(e.to_string()))?;
+        let mut headers = HashMap::new();
+        if let Some(token) = &self.token {
+            headers.insert("Authorization".to_owned(), format!("Bearer {}", token));
+        }
+        let mut checksum = None;
+        for _ in 0..5 {
+            let mut request = client.head(url.clone());
+            if url.host_str() == Some("huggingface.co")
+                && let Some(token) = &self.token
+            {
+                request = request.bearer_auth(token);
+            }
+            let response = request.send().await?;
+            if !response.status().is_redirection() {
+                response.error_for_status()?;
+                break;
+            }
+            let linked = response
+                .headers()
+                .get("X-Linked-Etag")
+                .and_then(|v| v.to_str().ok())
+                .map(|e| e.trim_matches('"').to_owned());
+            if let Some(sha256) = linked.filter(|e| e.len() == 64) {
+                checksum = Some(format!("sha256:{}", sha256));
+            }
+            let location = response
+                .headers()
+                .get(LOCATION)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| DownloadError::Hub("redirect without location".to_owned()))?;
+            url = url
+                .join(location)
+                .map_err(|e| DownloadError::Hub(e.to_string()))?;
+            if url.host_str() != Some("huggingface.co") {
+                headers.clear();
+                break;
+            }
+        }
+        Ok(Resolved {
+            urls: vec![url.to_string()],
+            headers,
+            checksum,
+        })
+    }
+}
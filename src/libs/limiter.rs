@@ -0,0 +1,47 @@
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+pub struct Limiter {
+    rate: AtomicU64,
+    bucket: Mutex<(f64, Instant)>,
+}
+
+impl Limiter {
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate: AtomicU64::new(rate),
+            bucket: Mutex::new((0.0, Instant::now())),
+        }
+    }
+
+    pub fn rate(&self) -> u64 {
+        self.rate.load(Ordering::Relaxed)
+    }
+
+    pub fn set_rate(&self, rate: u64) {
+        self.rate.store(rate, Ordering::Relaxed);
+    }
+
+    pub async fn acquire(&self, bytes: u64) {
+        let rate = match self.rate() {
+            0 => return,
+            rate => rate as f64,
+        };
+        let debt = {
+            let mut bucket = self.bucket.lock().unwrap();
+            let now = Instant::now();
+            let refill = now.duration_since(bucket.1).as_secs_f64() * rate;
+            bucket.0 = (bucket.0 + refill).min(rate) - bytes as f64;
+            bucket.1 = now;
+            -bucket.0
+        };
+        if debt > 0.0 {
+            tokio::time::sleep(Duration::from_secs_f64(debt / rate)).await;
+        }
+    }
+}
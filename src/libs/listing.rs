@@ -0,0 +1,86 @@
+use super::{
+    DownloadError,
+    filename::{decode, glob},
+    sanitize,
+};
+use percent_encoding::percent_decode_str;
+use reqwest::{Client, Url};
+use std::{collections::HashSet, path::PathBuf};
+
+const MAX_DEPTH: usize = 16;
+
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub url: String,
+    pub path: PathBuf,
+}
+
+pub async fn crawl(
+    client: &Client,
+    root: &str,
+    accept: &[String],
+) -> Result<Vec<Entry>, DownloadError> {
+    let root = Url::parse(root).map_err(|_| DownloadError::InvalidResponse)?;
+    let base = root.path().to_owned();
+    let mut pending = vec![(root, 0)];
+    let mut seen = HashSet::new();
+    let mut entries = Vec::new();
+    while let Some((dir, depth)) = pending.pop() {
+        if !seen.insert(dir.clone()) {
+            continue;
+        }
+        let page = client.get(dir.clone()).send().await?.error_for_status()?;
+        for link in links(&page.text().await?) {
+            let Ok(mut url) = dir.join(link) else {
+                continue;
+            };
+            url.set_fragment(None);
+            if url.query().is_some()
+                || url.origin() != dir.origin()
+                || !url.path().starts_with(&base)
+                || url.path().len() <= dir.path().len()
+            {
+                continue;
+            }
+            if url.path().ends_with('/') {
+                if depth < MAX_DEPTH {
+                    pending.push((url, depth + 1));
+                }
+                continue;
+            }
+            let path = segments(&url.path()[base.len()..]);
+            let name = path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            if accept.is_empty() || accept.iter().any(|p| glob(p, &name)) {
+                entries.push(Entry {
+                    url: url.to_string(),
+                    path,
+                });
+            }
+        }
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    entries.dedup_by(|a, b| a.url == b.url);
+    Ok(entries)
+}
+
+pub fn mirror_path(url: &str) -> Option<PathBuf> {
+    let url = Url::parse(url).ok()?;
+    Some(PathBuf::from(sanitize(url.host_str()?)).join(segments(url.path())))
+}
+
+fn segments(path: &str) -> PathBuf {
+    path.split('/')
+        .filter(|s| !s.is_empty())
+        .map(|s| sanitize(&decode(&percent_decode_str(s).collect::<Vec<_>>(), None)))
+        .collect()
+}
+
+fn links(html: &str) -> impl Iterator<Item = &str> {
+    html.split("href=").skip(1).filter_map(|rest| {
+        let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        rest[1..].split(quote).next()
+    })
+}
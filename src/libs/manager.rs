@@ -0,0 +1,657 @@
+use super::{
+    DownloadError, Downloader, DownloaderConfig, Progress, Status, consts::MAX_PARALLEL,
+    gate::Gate, safe_join,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+const MAX_ACTIVE: usize = 3;
+const MAX_CONNECTIONS: usize = 64;
+// Share of the rate limit in use at which bandwidth counts as short
+const SATURATED: f64 = 0.9;
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    /// Share of the bandwidth budget relative to the other priorities.
+    fn weight(self) -> u64 {
+        match self {
+            Self::Low => 1,
+            Self::Normal => 4,
+            Self::High => 16,
+        }
+    }
+}
+
+/// What the manager does to the lowest-priority download while one above
+/// it is short of bandwidth or connections, until nothing outranks it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Preemption {
+    Off,
+    /// Drops it to one connection.
+    #[default]
+    Throttle,
+    /// Pauses it. A paused download keeps its open connections, so while
+    /// the connection ceiling is full it is throttled instead.
+    Pause,
+}
+
+/// A download waiting for a slot, and what the queue file remembers of it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Queued {
+    pub url: String,
+    pub output: Option<String>,
+    #[serde(default)]
+    pub dir: Option<PathBuf>,
+    #[serde(default)]
+    pub priority: Priority,
+    #[serde(default)]
+    pub paused: bool,
+    #[serde(default)]
+    pub downloaded: u64,
+    #[serde(default)]
+    pub total: u64,
+}
+
+impl Queued {
+    pub fn new(url: &str, priority: Priority) -> Self {
+        Self {
+            url: url.to_owned(),
+            output: None,
+            dir: None,
+            priority,
+            paused: false,
+            downloaded: 0,
+            total: 0,
+        }
+    }
+}
+
+/// Runs several downloads in one process over a single shared client.
+/// `max_active` caps the downloads running at once, `max_parallel` the
+/// connections each of them opens, and `max_connections` all of those
+/// connections together.
+pub struct DownloadManager {
+    config: DownloaderConfig,
+    downloads: Vec<(u64, Downloader)>,
+    priorities: HashMap<u64, Priority>,
+    queue: VecDeque<Queued>,
+    max_active: usize,
+    rate_limit: u64,
+    preemption: Preemption,
+    // Held back downloads: their connection count before, and whether they
+    // were paused
+    preempted: HashMap<u64, (usize, bool)>,
+    store: Option<PathBuf>,
+    next: u64,
+}
+
+impl DownloadManager {
+    pub fn new(config: DownloaderConfig) -> Result<Self, DownloadError> {
+        let client = config.client()?;
+        let mut config = config.shared_client(client);
+        config.connections = Some(Arc::new(Gate::new(MAX_CONNECTIONS)));
+        Ok(Self {
+            config,
+            downloads: Vec::new(),
+            priorities: HashMap::new(),
+            queue: VecDeque::new(),
+            max_active: MAX_ACTIVE,
+            rate_limit: 0,
+            preemption: Preemption::default(),
+            preempted: HashMap::new(),
+            store: None,
+            next: 0,
+        })
+    }
+
+    /// Picks up the queue saved at `path`, and keeps saving it there.
+    /// Unfinished downloads go back in the queue and resume from their part
+    /// files once a slot frees up.
+    pub fn restore(
+        config: DownloaderConfig,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, DownloadError> {
+        let path = path.as_ref();
+        let mut manager = Self::new(config)?;
+        if path.exists() {
+            let queue: Vec<Queued> =
+                serde_json::from_slice(&std::fs::read(path)?).map_err(std::io::Error::from)?;
+            manager.queue.extend(queue);
+        }
+        manager.store = Some(path.to_path_buf());
+        Ok(manager)
+    }
+
+    /// Writes the queue, with how far each download got, beside the store
+    /// and renames it over, so a crash never leaves half a file.
+    fn save(&self) -> Result<(), DownloadError> {
+        let Some(path) = &self.store else {
+            return Ok(());
+        };
+        let running = self.downloads.iter().filter_map(|(id, d)| {
+            let progress = d.tracer.progress();
+            (progress.status != Status::Finished).then(|| Queued {
+                url: d.url.clone(),
+                output: std::path::absolute(&d.output)
+                    .ok()
+                    .map(|p| p.to_string_lossy().into_owned()),
+                dir: None,
+                priority: self.priority(*id),
+                paused: d.paused(),
+                downloaded: progress.downloaded,
+                total: progress.total,
+            })
+        });
+        let queue: Vec<_> = running.chain(self.queue.iter().cloned()).collect();
+        let json = serde_json::to_vec_pretty(&queue).map_err(std::io::Error::from)?;
+        let staged = PathBuf::from(format!("{}.tmp", path.display()));
+        let mut file = std::fs::File::create(&staged)?;
+        file.write_all(&json)?;
+        file.sync_all()?;
+        drop(file);
+        Ok(std::fs::rename(staged, path)?)
+    }
+
+    pub fn config(&self) -> &DownloaderConfig {
+        &self.config
+    }
+
+    /// Probes `url`, settles its output name and starts it.
+    pub async fn add(&mut self, url: &str) -> Result<u64, DownloadError> {
+        self.add_with_priority(url, Priority::Normal).await
+    }
+
+    pub async fn add_with_priority(
+        &mut self,
+        url: &str,
+        priority: Priority,
+    ) -> Result<u64, DownloadError> {
+        self.start(Queued::new(url, priority)).await
+    }
+
+    async fn start(&mut self, entry: Queued) -> Result<u64, DownloadError> {
+        let mut downloader = Downloader::new_with_config(&entry.url, self.config.clone()).await?;
+        let inferred = Path::new(&downloader.output);
+        match (entry.dir, entry.output) {
+            (None, None) => {}
+            // Where a saved queue left it
+            (None, Some(output)) if Path::new(&output).is_absolute() => {
+                downloader.set_output(output)?
+            }
+            // Names from a list stay inside the directory it would have used
+            (dir, name) => {
+                let base = std::path::absolute(inferred.parent().unwrap_or(Path::new("")))?;
+                let dir = dir.map_or(base.clone(), |d| safe_join(&base, &d.to_string_lossy()));
+                let name = name.unwrap_or_else(|| {
+                    inferred
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into_owned()
+                });
+                downloader.set_output(safe_join(dir, &name))?;
+            }
+        }
+        downloader.start();
+        let id = self.insert(downloader);
+        self.set_priority(id, entry.priority)?;
+        Ok(id)
+    }
+
+    /// Takes over a downloader prepared elsewhere, started or not.
+    pub fn insert(&mut self, downloader: Downloader) -> u64 {
+        let id = self.next;
+        self.next += 1;
+        self.downloads.push((id, downloader));
+        id
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Downloader> {
+        self.downloads
+            .iter()
+            .find(|(i, _)| *i == id)
+            .map(|(_, d)| d)
+    }
+
+    pub fn get_mut(&mut self, id: u64) -> Option<&mut Downloader> {
+        self.downloads
+            .iter_mut()
+            .find(|(i, _)| *i == id)
+            .map(|(_, d)| d)
+    }
+
+    pub fn list(&self) -> impl Iterator<Item = (u64, &Downloader)> {
+        self.downloads.iter().map(|(id, d)| (*id, d))
+    }
+
+    pub fn priority(&self, id: u64) -> Priority {
+        self.priorities.get(&id).copied().unwrap_or_default()
+    }
+
+    pub fn set_priority(&mut self, id: u64, priority: Priority) -> Result<(), DownloadError> {
+        self.priorities.insert(id, priority);
+        self.rebalance();
+        self.save()
+    }
+
+    pub fn rate_limit(&self) -> u64 {
+        self.rate_limit
+    }
+
+    /// Caps all running downloads together, split by priority; 0 lifts it.
+    pub fn set_rate_limit(&mut self, bytes_per_sec: u64) {
+        self.rate_limit = bytes_per_sec;
+        match bytes_per_sec {
+            0 => self.downloads.iter().for_each(|(_, d)| d.set_rate_limit(0)),
+            _ => self.rebalance(),
+        }
+    }
+
+    fn rebalance(&self) {
+        if self.rate_limit == 0 {
+            return;
+        }
+        let running = || {
+            self.downloads
+                .iter()
+                .filter(|(_, d)| d.running() && !d.paused())
+        };
+        let weights: u64 = running().map(|(id, _)| self.priority(*id).weight()).sum();
+        for (id, downloader) in running() {
+            let share = self.rate_limit * self.priority(*id).weight() / weights;
+            downloader.set_rate_limit(share.max(1));
+        }
+    }
+
+    pub fn preemption(&self) -> Preemption {
+        self.preemption
+    }
+
+    pub fn set_preemption(&mut self, preemption: Preemption) {
+        self.preemption = preemption;
+    }
+
+    /// Lets held back downloads go once nothing running outranks them, and
+    /// holds back one more while a higher one is short of bandwidth or
+    /// connections.
+    fn preempt(&mut self) {
+        let running = |id: u64| self.get(id).is_some_and(Downloader::running);
+        let top = self
+            .downloads
+            .iter()
+            .filter(|(id, d)| d.running() && !self.preempted.contains_key(id))
+            .map(|(id, _)| self.priority(*id))
+            .max();
+        let released: Vec<_> = self
+            .preempted
+            .keys()
+            .filter(|id| {
+                self.preemption == Preemption::Off
+                    || !running(**id)
+                    || top.is_none_or(|top| self.priority(**id) >= top)
+            })
+            .copied()
+            .collect();
+        for id in released {
+            let Some((parallel, paused)) = self.preempted.remove(&id) else {
+                continue;
+            };
+            if let Some(downloader) = self.get(id) {
+                downloader.set_max_parallel(parallel);
+                if paused {
+                    downloader.resume();
+                }
+            }
+        }
+        let Some(top) = top.filter(|_| self.preemption != Preemption::Off) else {
+            return;
+        };
+        let full = self
+            .config
+            .connections
+            .as_ref()
+            .is_some_and(|c| c.active() >= c.limit());
+        let speed: f64 = self
+            .downloads
+            .iter()
+            .filter(|(_, d)| d.running())
+            .map(|(_, d)| d.speed())
+            .sum();
+        let short = self.rate_limit > 0 && speed >= self.rate_limit as f64 * SATURATED;
+        if !full && !short {
+            return;
+        }
+        let victim = self
+            .downloads
+            .iter()
+            .filter(|(id, d)| {
+                d.running()
+                    && !d.paused()
+                    && !self.preempted.contains_key(id)
+                    && self.priority(*id) < top
+            })
+            .min_by_key(|(id, _)| self.priority(*id));
+        if let Some((id, downloader)) = victim {
+            let pause = self.preemption == Preemption::Pause && !full;
+            self.preempted
+                .insert(*id, (downloader.max_parallel(), pause));
+            downloader.set_max_parallel(1);
+            if pause {
+                downloader.pause();
+            }
+        }
+    }
+
+    pub fn max_active(&self) -> usize {
+        self.max_active
+    }
+
+    pub fn set_max_active(&mut self, max: usize) {
+        self.max_active = max.max(1);
+    }
+
+    pub fn max_parallel(&self) -> usize {
+        self.config.max_parallel
+    }
+
+    /// Connections per download, for those running and those to come.
+    pub fn set_max_parallel(&mut self, max: usize) {
+        self.config.max_parallel = max.clamp(1, MAX_PARALLEL);
+        self.downloads
+            .iter()
+            .for_each(|(_, d)| d.set_max_parallel(self.config.max_parallel));
+    }
+
+    pub fn max_connections(&self) -> usize {
+        self.config.connections.as_ref().map_or(0, |c| c.limit())
+    }
+
+    /// Connections of all downloads started here together. Downloaders
+    /// handed to `insert` keep only their own limit.
+    pub fn set_max_connections(&mut self, max: usize) {
+        if let Some(connections) = &self.config.connections {
+            connections.set_limit(max);
+        }
+    }
+
+    /// Queues `url` to start once fewer than `max_active` downloads run.
+    pub fn enqueue(&mut self, url: &str) -> Result<(), DownloadError> {
+        self.enqueue_with_priority(url, Priority::Normal)
+    }
+
+    /// Queues `url` ahead of everything with a lower priority.
+    pub fn enqueue_with_priority(
+        &mut self,
+        url: &str,
+        priority: Priority,
+    ) -> Result<(), DownloadError> {
+        self.push(Queued::new(url, priority));
+        self.save()
+    }
+
+    fn push(&mut self, entry: Queued) {
+        let at = self
+            .queue
+            .iter()
+            .position(|q| q.priority < entry.priority)
+            .unwrap_or(self.queue.len());
+        self.queue.insert(at, entry);
+    }
+
+    /// Enqueues a list file: one URL per line, `#` comments, and indented
+    /// `out=<name>` / `dir=<path>` lines for the URL above them. Both are
+    /// relative to where the download would land otherwise and cannot leave
+    /// it.
+    pub fn import_list(&mut self, path: impl AsRef<Path>) -> Result<usize, DownloadError> {
+        let mut entries: Vec<Queued> = Vec::new();
+        for line in std::fs::read_to_string(path)?.lines() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            if !line.starts_with(char::is_whitespace) {
+                entries.push(Queued::new(trimmed, Priority::Normal));
+                continue;
+            }
+            let Some(entry) = entries.last_mut() else {
+                continue;
+            };
+            match trimmed.split_once('=') {
+                Some((_, value)) if value.trim().is_empty() => {}
+                Some(("out", name)) => {
+                    let name = safe_join("", name.trim());
+                    entry.output = Some(name.to_string_lossy().into_owned());
+                }
+                Some(("dir", dir)) => entry.dir = Some(safe_join("", dir.trim())),
+                _ => {}
+            }
+        }
+        let count = entries.len();
+        entries.into_iter().for_each(|entry| self.push(entry));
+        self.save()?;
+        Ok(count)
+    }
+
+    pub fn queued(&self) -> impl Iterator<Item = &Queued> {
+        self.queue.iter()
+    }
+
+    pub fn active(&self) -> usize {
+        self.downloads.iter().filter(|(_, d)| d.running()).count()
+    }
+
+    /// Starts queued URLs into free slots, returning those that failed to
+    /// probe, and saves the queue with the progress made so far.
+    pub async fn pump(&mut self) -> Result<Vec<(String, DownloadError)>, DownloadError> {
+        let mut failed = Vec::new();
+        while self.active() < self.max_active
+            && let Some(at) = self.queue.iter().position(|q| !q.paused)
+            && let Some(entry) = self.queue.remove(at)
+        {
+            let url = entry.url.clone();
+            if let Err(e) = self.start(entry).await {
+                failed.push((url, e));
+            }
+        }
+        self.preempt();
+        self.rebalance();
+        self.save()?;
+        Ok(failed)
+    }
+
+    /// Keeps starting queued URLs as slots free up until nothing is running
+    /// and all that is left in the queue is paused.
+    pub async fn run(&mut self) -> Result<Vec<(String, DownloadError)>, DownloadError> {
+        let mut failed = Vec::new();
+        loop {
+            failed.extend(self.pump().await?);
+            if self.queue.iter().all(|q| q.paused) && self.active() == 0 {
+                return Ok(failed);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Stops the download if it is still running and forgets it, deleting
+    /// its part and state files when `delete` is set.
+    pub async fn remove(&mut self, id: u64, delete: bool) -> Result<(), DownloadError> {
+        let at = self
+            .downloads
+            .iter()
+            .position(|(i, _)| *i == id)
+            .ok_or(DownloadError::UnknownDownload(id))?;
+        let (_, mut downloader) = self.downloads.remove(at);
+        self.priorities.remove(&id);
+        self.preempted.remove(&id);
+        self.save()?;
+        // One that already ended has shown how through its status, so only
+        // the clean-up is left to report
+        if !downloader.running() {
+            downloader.handle.take();
+        }
+        downloader.cancel(delete).await
+    }
+
+    /// Combined progress, with the status of the most active download.
+    pub fn progress(&self) -> Progress {
+        let rank = |status: Status| match status {
+            Status::Running => 0,
+            Status::WaitingForSpace => 1,
+            Status::Paused => 2,
+            Status::Pending => 3,
+            Status::Failed => 4,
+            Status::Cancelled => 5,
+            Status::Finished => 6,
+        };
+        self.downloads
+            .iter()
+            .map(|(_, d)| d.tracer.progress())
+            .fold(None, |total: Option<Progress>, p| {
+                Some(match total {
+                    None => p,
+                    Some(t) => Progress {
+                        downloaded: t.downloaded + p.downloaded,
+                        total: t.total + p.total,
+                        speed: t.speed + p.speed,
+                        status: match rank(p.status) < rank(t.status) {
+                            true => p.status,
+                            false => t.status,
+                        },
+                    },
+                })
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn pause_all(&mut self) -> Result<(), DownloadError> {
+        self.downloads
+            .iter()
+            .filter(|(_, d)| d.running())
+            .for_each(|(_, d)| d.pause());
+        self.queue.iter_mut().for_each(|q| q.paused = true);
+        self.save()
+    }
+
+    pub fn resume_all(&mut self) -> Result<(), DownloadError> {
+        self.downloads
+            .iter()
+            .filter(|(_, d)| d.running())
+            .for_each(|(_, d)| d.resume());
+        self.queue.iter_mut().for_each(|q| q.paused = false);
+        self.save()
+    }
+
+    /// Saves every running download so it resumes next time.
+    pub async fn shutdown(&mut self) -> Result<(), DownloadError> {
+        for (_, downloader) in self.downloads.iter_mut().filter(|(_, d)| d.running()) {
+            downloader.shutdown().await?;
+        }
+        self.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queue_survives_a_restart() {
+        let dir = std::env::temp_dir().join(format!("stupidl-queue-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (list, store) = (dir.join("list.txt"), dir.join("queue.json"));
+        std::fs::write(
+            &list,
+            "https://a.example/1\n  out=one.bin\nhttps://b.example/2\n",
+        )
+        .unwrap();
+        let mut manager = DownloadManager::restore(DownloaderConfig::new(), &store).unwrap();
+        assert_eq!(manager.import_list(&list).unwrap(), 2);
+        manager
+            .enqueue_with_priority("https://c.example/3", Priority::High)
+            .unwrap();
+        assert!(!dir.join("queue.json.tmp").exists());
+        let restored = DownloadManager::restore(DownloaderConfig::new(), &store).unwrap();
+        let urls: Vec<_> = restored.queued().map(|q| q.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://c.example/3",
+                "https://a.example/1",
+                "https://b.example/2"
+            ]
+        );
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn list_names_cannot_escape() {
+        let dir = std::env::temp_dir().join(format!("stupidl-list-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let list = dir.join("list.txt");
+        std::fs::write(
+            &list,
+            "https://a.example/1\n  out=../../evil.bin\n  dir=/etc/../cron.d\nhttps://b.example/2\n  out=C:\\x.exe\n",
+        )
+        .unwrap();
+        let mut manager = DownloadManager::new(DownloaderConfig::new()).unwrap();
+        manager.import_list(&list).unwrap();
+        let queued: Vec<_> = manager.queued().collect();
+        assert_eq!(queued[0].output.as_deref(), Some("evil.bin"));
+        assert_eq!(queued[0].dir, Some(Path::new("etc").join("cron.d")));
+        assert_eq!(
+            queued[1].output.as_deref(),
+            Some(Path::new("C_").join("x.exe").to_str().unwrap())
+        );
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[tokio::test]
+    async fn removing_an_unknown_id_says_so() {
+        let mut manager = DownloadManager::new(DownloaderConfig::new()).unwrap();
+        assert!(matches!(
+            manager.remove(7, true).await,
+            Err(DownloadError::UnknownDownload(7))
+        ));
+    }
+
+    #[tokio::test]
+    async fn downloads_share_one_connection_ceiling() {
+        let mut manager = DownloadManager::new(DownloaderConfig::new()).unwrap();
+        manager.set_max_connections(1);
+        let (one, other) = (manager.config().clone(), manager.config().clone());
+        let held = one.connections.as_ref().unwrap().acquire().await;
+        let other = other.connections.unwrap();
+        assert_eq!(other.active(), 1);
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), other.acquire())
+                .await
+                .is_err()
+        );
+        drop(held);
+        assert_eq!(manager.max_connections(), 1);
+    }
+
+    #[test]
+    fn save_errors_are_reported() {
+        let dir = std::env::temp_dir().join(format!("stupidl-nostore-{}", std::process::id()));
+        let store = dir.join("missing").join("queue.json");
+        let mut manager = DownloadManager::restore(DownloaderConfig::new(), &store).unwrap();
+        assert!(manager.enqueue("https://a.example/1").is_err());
+    }
+}
@@ -1,21 +1,120 @@
+mod actions;
+mod attrs;
+mod category;
+mod checksum;
+mod chunks;
+mod compare;
+mod config;
 mod consts;
+mod cookies;
+mod events;
 mod filename;
+mod gate;
+mod handle;
+mod history;
+mod hub;
+mod limiter;
+mod listing;
+mod manager;
+mod notify;
+mod oci;
+mod plan;
+mod plugin;
+mod policy;
+mod prealloc;
+mod quota;
+mod release;
+mod sandbox;
+mod scan;
+#[cfg(feature = "scripting")]
+mod script;
+mod sink;
+mod speedtest;
+mod state;
+mod steal;
+mod template;
+mod trace;
+mod tracer;
+mod tune;
+mod units;
+mod writer;
+mod zip;
+pub use actions::{Action, Actions};
+pub use category::Category;
+pub use checksum::{Algorithm, Checksum, Hasher};
+pub use chunks::ChunkState;
+pub use compare::Comparison;
+pub use config::{
+    ArtifactDir, Artifacts, Auth, ChunkOrder, ChunkStrategy, Collision, DownloaderConfig,
+    HostOverride, OnFailure, Protocol, ProxyConfig, ProxyMode,
+};
+pub use cookies::load_cookies;
+pub use events::Event;
+pub use filename::{glob, safe_join, sanitize};
+pub use handle::DownloadHandle;
+pub use history::Record;
+pub use hub::HubFile;
+pub use listing::{Entry, crawl, mirror_path};
+pub use manager::{DownloadManager, Preemption, Priority, Queued};
+pub use notify::Notifier;
+pub use oci::Blob;
+pub use plan::Plan;
+pub use plugin::{Plugin, Resolved, resolve};
+pub use policy::Policy;
+pub use quota::Quota;
+pub use release::{Forge, Release};
+pub use sandbox::Sandbox;
+pub use scan::Scanner;
+#[cfg(feature = "scripting")]
+pub use script::{Hooks, Verdict};
+pub use sink::ProgressSink;
+pub use speedtest::{Sample, SpeedTest, TEST_URL};
+pub use state::State;
+pub use template::Vars;
+pub use tracer::{Progress, ProgressKind, Status};
+pub use units::{Format, Units};
+pub use zip::Member;
 use {
-    consts::*,
-    filename::filename_from,
+    checksum::Incremental,
+    chunks::ChunkMap,
+    consts::{
+        CANCEL_GRACE, MAX_PARALLEL, MIN_STEAL, OVERLAP, RETRY_BACKOFF, SMALL_FILE, STALL_RETRIES,
+    },
+    events::{EventLog, Recorder},
+    filename::{filename_from, unique},
     futures_util::stream::{StreamExt, iter},
-    reqwest::Client,
-    std::sync::{
-        Arc,
-        atomic::{AtomicU64, Ordering},
+    gate::Gate,
+    limiter::Limiter,
+    reqwest::{
+        Client, StatusCode,
+        header::{
+            ACCEPT_ENCODING, ACCEPT_RANGES, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, ETAG,
+            HeaderMap, HeaderName, LAST_MODIFIED, RANGE, REFERER,
+        },
     },
+    state::{Journal, SAMPLE, fingerprint},
+    std::{
+        collections::HashMap,
+        path::PathBuf,
+        sync::{
+            Arc, Mutex, OnceLock, PoisonError,
+            atomic::{AtomicBool, Ordering},
+        },
+        time::Duration,
+    },
+    steal::{Board, Part},
     thiserror::Error,
     tokio::{
-        fs::{File, OpenOptions},
-        io::{AsyncSeekExt, AsyncWriteExt, BufWriter},
-        sync::watch::{Receiver, Sender},
+        fs::File,
+        sync::{
+            SemaphorePermit,
+            watch::{Receiver, Sender},
+        },
         task::{JoinError, JoinHandle},
     },
+    trace::Trace,
+    tracer::Tracer,
+    writer::{Output, WRITE_BUFFER, Writer},
 };
 
 #[derive(Error, Debug)]
@@ -37,84 +136,583 @@ pub enum DownloadError {
 
     #[error("Task join failed: {0}")]
     Join(#[from] JoinError),
-}
 
-#[derive(Clone)]
-struct Tracer {
-    pub total_size: u64,
-    counter: Arc<AtomicU64>,
-    sender: Sender<u64>,
-}
+    #[error("Download cancelled")]
+    Cancelled,
 
-impl Tracer {
-    fn new(total_size: u64) -> Self {
-        Self {
-            total_size,
-            counter: Arc::new(AtomicU64::new(0)),
-            sender: Sender::new(0),
-        }
-    }
+    #[error("No matching partial download at {0}")]
+    RebindMismatch(String),
 
-    fn add(&self, size: u64) {
-        self.counter.fetch_add(size, Ordering::Relaxed);
-        self.sender
-            .send(self.counter.load(Ordering::Relaxed) * 100 / self.total_size)
-            .unwrap();
-    }
+    #[error("Plugin failed: {0}")]
+    Plugin(String),
+
+    #[error("Notification failed: {0}")]
+    Notify(String),
+
+    #[error("Release lookup failed: {0}")]
+    Release(String),
+
+    #[error("Hub lookup failed: {0}")]
+    Hub(String),
+
+    #[error("Registry request failed: {0}")]
+    Registry(String),
+
+    #[error("Policy violation: {0}")]
+    PolicyViolation(String),
+
+    #[error("Invalid policy: {0}")]
+    InvalidPolicy(String),
+
+    #[error("Scan flagged the file, quarantined as {0}")]
+    ScanFailed(String),
+
+    #[error("Scanner failed: {0}")]
+    ScanError(String),
+
+    #[error("Output path escapes the sandbox: {0}")]
+    OutsideSandbox(String),
+
+    #[error("Invalid sandbox: {0}")]
+    InvalidSandbox(String),
+
+    #[error("Completion action failed: {0}")]
+    Action(String),
+
+    #[error("\tChunk {0} failed: connection stalled")]
+    Stalled(u64),
+
+    #[error("\tChunk {0} failed: remote content changed")]
+    RemoteChanged(u64),
+
+    #[error("\tChunk {0} failed: server ignored the range")]
+    RangeIgnored(u64),
+
+    #[error("\tMirror {0} serves different content")]
+    MirrorContentMismatch(String),
+
+    #[error("File already exists: {0}")]
+    AlreadyExists(String),
+
+    #[error("Not enough disk space: {needed} bytes needed, {available} available")]
+    InsufficientSpace { needed: u64, available: u64 },
+
+    #[error("Checksum mismatch: expected {expected}, got {actual}")]
+    ChecksumMismatch { expected: String, actual: String },
+
+    #[error("Invalid archive: {0}")]
+    Archive(String),
+
+    #[error("No download with id {0}")]
+    UnknownDownload(u64),
+
+    #[cfg(feature = "scripting")]
+    #[error("Script hook failed: {0}")]
+    Script(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Complete,
+    PartialSuccess { missing_ranges: Vec<(u64, u64)> },
 }
 
 pub struct Downloader {
-    handle: Option<JoinHandle<Result<(), DownloadError>>>,
+    handle: Option<JoinHandle<Result<Outcome, DownloadError>>>,
     client: Client,
     tracer: Tracer,
+    pause: Sender<bool>,
+    stop: Arc<AtomicBool>,
+    limiter: Arc<Limiter>,
+    gate: Arc<Gate>,
+    state: State,
+    config: DownloaderConfig,
+    headers: HeaderMap,
+    chunk_headers: Arc<OnceLock<HeaderMap>>,
+    trace: Arc<Trace>,
+    chunks: Arc<ChunkMap>,
+    events: Arc<EventLog>,
+    placed: Arc<Mutex<Option<String>>>,
+    sha256: Arc<Mutex<Option<String>>>,
+    prefetched: Option<reqwest::Response>,
     pub url: String,
+    pub mirrors: Vec<String>,
+    pub final_url: String,
     pub output: String,
     pub total_chunk: u64,
+    pub resumed: bool,
 }
 
 impl Downloader {
     pub async fn new(url: &str) -> Result<Self, DownloadError> {
-        let client = Client::builder().user_agent(UA).build()?;
-        let response = client.head(url).send().await?;
-        let output = filename_from(&response);
-        let total_size = response
-            .headers()
-            .get(reqwest::header::CONTENT_LENGTH)
-            .and_then(|v| v.to_str().ok())
-            .and_then(|v| v.parse().ok())
+        Self::with_headers(url, HeaderMap::new()).await
+    }
+
+    pub async fn with_headers(url: &str, headers: HeaderMap) -> Result<Self, DownloadError> {
+        Self::new_with_config(url, DownloaderConfig::new().headers(headers)).await
+    }
+
+    pub async fn new_with_config(
+        url: &str,
+        config: DownloaderConfig,
+    ) -> Result<Self, DownloadError> {
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_owned))
             .unwrap_or_default();
-        if total_size <= 0 {
-            return Err(DownloadError::InvalidResponse);
+        // Host rules hold before anything is sent
+        if let Some(policy) = &config.policy {
+            policy.check(url, "")?;
         }
-        let total_chunk = match response
-            .headers()
-            .get("Accept-Ranges")
-            .map(|v| v.as_bytes())
-        {
-            Some(b"bytes") => 1.max(total_size / MB),
-            _ => 1,
+        let client = config.client()?;
+        let trace = Arc::new(Trace::new(config.trace));
+        // The probe asks for the first SMALL_FILE bytes, so a small file
+        // arrives whole and is not requested twice
+        let probe = client
+            .get(url)
+            .header(RANGE, format!("bytes=0-{}", SMALL_FILE - 1))
+            .header(ACCEPT_ENCODING, "identity");
+        // An error page is never the file, whatever its length
+        let response = trace
+            .send(&client, "probe", probe)
+            .await?
+            .error_for_status()?;
+        // Checked on where the redirects led, before any of the body is read
+        if let Some(policy) = &config.policy {
+            policy.check(
+                response.url().as_str(),
+                &filename_from(&response, config.charset),
+            )?;
+        }
+        // Overrides follow where the redirects led, e.g. github.com to
+        // objects.githubusercontent.com
+        let config = config
+            .for_host(response.url().host_str().unwrap_or(&host))
+            .for_version(response.version());
+        let mut output = config.destination(&filename_from(&response, config.charset));
+        if let Some(sandbox) = &config.sandbox {
+            output = sandbox.check(&output)?.to_string_lossy().into_owned();
+        }
+        if let Some(policy) = &config.policy {
+            policy.check(response.url().as_str(), &output)?;
+        }
+        let (total_size, ranged) = match response.status() {
+            StatusCode::PARTIAL_CONTENT => (
+                response
+                    .headers()
+                    .get(CONTENT_RANGE)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.rsplit_once('/'))
+                    .and_then(|(_, total)| total.parse().ok())
+                    .unwrap_or_default(),
+                true,
+            ),
+            _ => (
+                header_u64(&response, CONTENT_LENGTH),
+                response
+                    .headers()
+                    .get(ACCEPT_RANGES)
+                    .is_some_and(|v| v.as_bytes() == b"bytes"),
+            ),
+        };
+        let whole = match response.status() {
+            StatusCode::PARTIAL_CONTENT => total_size > 0 && total_size <= SMALL_FILE,
+            _ => total_size <= SMALL_FILE,
+        };
+        let chunk_size = match ranged && total_size > 0 {
+            true => config.strategy.chunk_size(total_size),
+            false => total_size,
+        };
+        let state = State {
+            url: url.to_owned(),
+            total_size,
+            chunk_size,
+            etag: response
+                .headers()
+                .get(reqwest::header::ETAG)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_owned),
+            last_modified: response
+                .headers()
+                .get(reqwest::header::LAST_MODIFIED)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_owned),
+            done: Vec::new(),
+            samples: Default::default(),
         };
-        Ok(Self {
+        let mut downloader = Self {
             handle: None,
             client,
             tracer: Tracer::new(total_size),
+            pause: Sender::new(false),
+            stop: Arc::new(AtomicBool::new(false)),
+            limiter: Arc::new(Limiter::new(config.rate_limit)),
+            gate: Arc::new(Gate::new(
+                tune::learned(&host)
+                    .filter(|_| config.auto_tune)
+                    .map_or(config.max_parallel, |n| n.min(config.max_parallel))
+                    .min(MAX_PARALLEL),
+            )),
+            state,
+            config,
+            headers: representation(&response, total_size),
+            chunk_headers: Arc::new(OnceLock::new()),
+            trace,
+            chunks: Arc::default(),
+            events: Arc::new(EventLog::new()),
+            placed: Arc::default(),
+            sha256: Arc::default(),
+            prefetched: None,
             url: url.to_owned(),
+            mirrors: vec![url.to_owned()],
+            final_url: response.url().to_string(),
             output: output.to_owned(),
-            total_chunk,
-        })
+            total_chunk: total_size.div_ceil(chunk_size.max(1)).max(1),
+            resumed: false,
+        };
+        downloader.chunks.reset(downloader.total_chunk, |_| false);
+        downloader.events.push(Event::Probe {
+            url: downloader.url.clone(),
+            final_url: downloader.final_url.clone(),
+            status: response.status().as_u16(),
+            total_size,
+            chunk_size,
+            total_chunk: downloader.total_chunk,
+            etag: downloader.state.etag.clone(),
+            last_modified: downloader.state.last_modified.clone(),
+        });
+        if whole && !downloader.config.compressed {
+            downloader.prefetched = Some(response);
+        }
+        downloader.settle()?;
+        Ok(downloader)
+    }
+
+    pub async fn new_multi(urls: &[&str]) -> Result<Self, DownloadError> {
+        Self::new_multi_with_config(urls, DownloaderConfig::new()).await
+    }
+
+    pub async fn new_multi_with_config(
+        urls: &[&str],
+        config: DownloaderConfig,
+    ) -> Result<Self, DownloadError> {
+        let (first, rest) = urls.split_first().ok_or(DownloadError::InvalidResponse)?;
+        let mut downloader = Self::new_with_config(first, config).await?;
+        let total_size = downloader.state.total_size;
+        for url in rest {
+            // Asked exactly like the probe, so a mirror that ignores ranges or
+            // serves another revision is dropped here rather than mid-chunk
+            let probe = downloader
+                .client
+                .get(*url)
+                .header(RANGE, format!("bytes=0-{}", SMALL_FILE - 1))
+                .header(ACCEPT_ENCODING, "identity");
+            let Ok(response) = downloader
+                .trace
+                .send(&downloader.client, "mirror", probe)
+                .await
+            else {
+                continue;
+            };
+            let allowed = downloader.config.policy.as_ref().is_none_or(|policy| {
+                policy
+                    .check(response.url().as_str(), &downloader.output)
+                    .is_ok()
+            });
+            let total = match response.status() {
+                StatusCode::PARTIAL_CONTENT => content_range(&response).and_then(|(.., t)| t),
+                _ if downloader.total_chunk > 1 => None,
+                _ => Some(header_u64(&response, CONTENT_LENGTH)),
+            };
+            // Validators only count when both sides send one
+            let agrees = |ours: &Option<String>, name: HeaderName| {
+                let theirs = response.headers().get(name).and_then(|v| v.to_str().ok());
+                ours.as_deref().zip(theirs).is_none_or(|(a, b)| a == b)
+            };
+            if allowed
+                && response.status().is_success()
+                && total == Some(total_size)
+                && agrees(&downloader.state.etag, ETAG)
+                && agrees(&downloader.state.last_modified, LAST_MODIFIED)
+            {
+                downloader.mirrors.push(url.to_string());
+            }
+        }
+        Ok(downloader)
+    }
+
+    pub fn settle(&mut self) -> Result<(), DownloadError> {
+        if !std::path::Path::new(&self.output).exists() {
+            return Ok(());
+        }
+        match self.config.collision {
+            Collision::Rename => self.output = unique(&self.output),
+            Collision::Overwrite => {}
+            Collision::Skip => return Err(DownloadError::AlreadyExists(self.output.clone())),
+        }
+        Ok(())
     }
 
     pub fn start(&mut self) {
-        self.handle.replace(tokio::spawn(download(
-            self.client.clone(),
-            self.url.clone(),
-            self.output.clone(),
-            self.total_chunk,
-            self.tracer.clone(),
-        )));
+        self.start_with(Arc::new(()));
+    }
+
+    pub fn start_with(&mut self, sink: Arc<dyn ProgressSink>) {
+        self.tracer.sink = Arc::new(Recorder {
+            log: self.events.clone(),
+            inner: sink,
+        });
+        self.trace
+            .attach(&self.config.artifacts.path_for(&self.output, "trace"));
+        let state = self.restore();
+        self.stop.store(false, Ordering::Relaxed);
+        self.tracer.set_status(Status::Running);
+        self.tracer.sink.on_start(&self.tracer.progress());
+        let job = Job {
+            client: self.client.clone(),
+            mirrors: Mirrors::new(self.mirrors.clone(), self.config.host_limit),
+            output: self.output.clone(),
+            part: self.part_path(&self.output),
+            collision: self.config.collision,
+            placed: self.placed.clone(),
+            sha256: self.sha256.clone(),
+            prefetched: self.prefetched.take(),
+            total_chunk: self.total_chunk,
+            chunk_size: state.chunk_size,
+            gate: self.gate.clone(),
+            connections: self.config.connections.clone(),
+            order: self.config.order,
+            stall_timeout: self.config.stall_timeout,
+            retries: self.config.retries,
+            no_index: self.config.no_index,
+            zone: self.config.zone_identifier.then(|| {
+                let referrer = self.config.headers.get(REFERER);
+                attrs::zone_identifier(&self.final_url, referrer.and_then(|v| v.to_str().ok()))
+            }),
+            compressed: self.config.compressed,
+            mmap: self.config.mmap,
+            quota: self.config.quota_for(&self.output),
+            sandbox: self.config.sandbox.clone(),
+            tracer: self.tracer.clone(),
+            pause: self.pause.subscribe(),
+            stop: self.stop.clone(),
+            limiter: self.limiter.clone(),
+            connection_rate: self.config.connection_rate,
+            journal: Journal::new(self.state_path(&self.output), state),
+            resumed: self.resumed,
+            checksum: self.config.checksum.clone(),
+            chunk_headers: self.chunk_headers.clone(),
+            trace: self.trace.clone(),
+            chunks: self.chunks.clone(),
+            partial: self.config.partial,
+            auto_tune: self.config.auto_tune,
+        };
+        let tracer = self.tracer.clone();
+        let cleanup = (self.config.on_failure == OnFailure::Delete)
+            .then(|| (self.part_path(&self.output), self.state_path(&self.output)));
+        self.handle.replace(tokio::spawn(async move {
+            let result = download(job).await;
+            match &result {
+                Ok(Outcome::Complete) => {
+                    tracer.set_status(Status::Finished);
+                    tracer.sink.on_finish(&tracer.progress());
+                }
+                Ok(Outcome::PartialSuccess { missing_ranges }) => {
+                    tracer.set_status(Status::Failed);
+                    tracer.sink.on_error(&DownloadError::ChunkFailure(format!(
+                        "{} ranges missing",
+                        missing_ranges.len()
+                    )));
+                }
+                Err(DownloadError::Cancelled) => tracer.set_status(Status::Paused),
+                Err(e) => {
+                    if let Some((part, state)) = cleanup {
+                        tokio::fs::remove_file(part).await.ok();
+                        tokio::fs::remove_file(state).await.ok();
+                    }
+                    tracer.set_status(Status::Failed);
+                    tracer.sink.on_error(e);
+                }
+            }
+            result
+        }));
+    }
+
+    pub fn plan(&self) -> Plan {
+        let done_chunk = State::load(&self.state_path(&self.output))
+            .filter(|s| self.total_chunk > 1 && s.resumes(&self.state))
+            .map_or(0, |s| {
+                (0..self.total_chunk).filter(|&i| s.is_done(i)).count() as u64
+            });
+        Plan {
+            mirrors: self.mirrors.clone(),
+            final_url: self.final_url.clone(),
+            output: self.output.clone(),
+            total_size: self.state.total_size,
+            chunk_size: self.state.chunk_size,
+            total_chunk: self.total_chunk,
+            done_chunk,
+            max_parallel: self.gate.limit(),
+            order: self.config.order,
+        }
+    }
+
+    /// Fetches `len` bytes at `start` into memory over the download's own
+    /// client, leaving the file job untouched.
+    pub async fn read_range(&self, start: u64, len: u64) -> Result<Vec<u8>, DownloadError> {
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        let end = start + len - 1;
+        let request = self
+            .client
+            .get(&self.url)
+            .header(RANGE, format!("bytes={}-{}", start, end))
+            .header(ACCEPT_ENCODING, "identity");
+        let response = self.trace.send(&self.client, "preview", request).await?;
+        let whole = start == 0 && response.status() == StatusCode::OK;
+        let matches = response.status() == StatusCode::PARTIAL_CONTENT
+            && content_range(&response).is_some_and(|(first, _, _)| first == start);
+        if !whole && !matches {
+            return Err(DownloadError::InvalidResponse);
+        }
+        let mut data = Vec::with_capacity(len.min(WRITE_BUFFER as u64) as usize);
+        let mut stream = response.bytes_stream();
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            let take = (len - data.len() as u64).min(chunk.len() as u64) as usize;
+            data.extend_from_slice(&chunk[..take]);
+            if data.len() as u64 == len {
+                break;
+            }
+        }
+        Ok(data)
+    }
+
+    /// The download's event history as JSON, for attaching to bug reports.
+    pub fn diagnostics(&self) -> String {
+        self.events.to_json()
+    }
+
+    pub fn chunks(&self) -> Vec<ChunkState> {
+        self.chunks.snapshot()
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn chunk_headers(&self) -> Option<&HeaderMap> {
+        self.chunk_headers.get()
+    }
+
+    pub fn expect(&mut self, checksum: Checksum) {
+        self.config.checksum = Some(checksum);
+    }
+
+    /// Relative paths land in the configured output directory.
+    /// Moves the destination, settling a collision with an existing file.
+    pub fn set_output(&mut self, path: impl AsRef<std::path::Path>) -> Result<(), DownloadError> {
+        let path = path.as_ref();
+        let path = match &self.config.output_dir {
+            Some(dir) if path.is_relative() => safe_join(dir, &path.to_string_lossy()),
+            _ => path.to_path_buf(),
+        };
+        self.output = self.confine(&path.to_string_lossy())?;
+        self.settle()
+    }
+
+    /// Resumes into an existing partial download, given its output or part
+    /// file.
+    pub fn rebind(&mut self, output: &str) -> Result<(), DownloadError> {
+        let output = match self.config.artifacts.output_for(output.as_ref(), "part") {
+            Some(output) => output.to_string_lossy().into_owned(),
+            None => output.to_owned(),
+        };
+        State::load(&self.state_path(&output))
+            .filter(|s| s.resumes(&self.state) && s.verify(&self.part_path(&output)))
+            .ok_or_else(|| DownloadError::RebindMismatch(output.clone()))?;
+        self.output = self.confine(&output)?;
+        Ok(())
+    }
+
+    fn state_path(&self, output: &str) -> PathBuf {
+        self.config.artifacts.path_for(output, "stdl")
+    }
+
+    fn part_path(&self, output: &str) -> PathBuf {
+        self.config.artifacts.path_for(output, "part")
+    }
+
+    fn restore(&mut self) -> State {
+        let state = State::load(&self.state_path(&self.output))
+            .filter(|s| self.total_chunk > 1 && s.resumes(&self.state))
+            .filter(|s| {
+                std::fs::metadata(self.part_path(&self.output))
+                    .is_ok_and(|m| m.len() == s.total_size)
+            })
+            .unwrap_or_else(|| self.state.clone());
+        self.resumed = !state.done.is_empty();
+        let downloaded = (0..self.total_chunk)
+            .filter(|&i| state.is_done(i))
+            .map(|i| chunk_range(i, state.chunk_size, state.total_size))
+            .map(|(start, end)| end - start + 1)
+            .sum();
+        self.tracer.reset(downloaded);
+        self.chunks.reset(self.total_chunk, |i| state.is_done(i));
+        state
+    }
+
+    pub fn pause(&self) {
+        self.pause.send_replace(true);
+        self.tracer.set_status(Status::Paused);
+    }
+
+    pub fn resume(&self) {
+        self.pause.send_replace(false);
+        self.tracer.set_status(Status::Running);
+    }
+
+    pub fn paused(&self) -> bool {
+        *self.pause.borrow()
+    }
+
+    /// Stops handing out chunks, lets the writer drain and saves the
+    /// journal, so the next start resumes from here.
+    pub async fn shutdown(&mut self) -> Result<(), DownloadError> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.pause.send_replace(false);
+        match self.join().await {
+            Err(DownloadError::Cancelled) => Ok(()),
+            result => result.map(drop),
+        }
+    }
+
+    pub fn rate_limit(&self) -> u64 {
+        self.limiter.rate()
+    }
+
+    pub fn set_rate_limit(&self, bytes_per_sec: u64) {
+        self.limiter.set_rate(bytes_per_sec);
+    }
+
+    pub fn max_parallel(&self) -> usize {
+        self.gate.limit()
+    }
+
+    pub fn set_max_parallel(&self, max: usize) {
+        self.gate.set_limit(max.min(MAX_PARALLEL));
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.tracer.speed()
+    }
+
+    pub fn eta(&self) -> Option<Duration> {
+        self.tracer.eta()
     }
 
-    pub fn watcher(&self) -> Receiver<u64> {
+    pub fn watcher(&self) -> Receiver<Progress> {
         self.tracer.sender.subscribe()
     }
 
@@ -122,63 +720,832 @@ impl Downloader {
         self.handle.as_ref().is_some_and(|h| !h.is_finished())
     }
 
-    pub async fn join(&mut self) -> Result<(), DownloadError> {
-        self.handle.take().unwrap().await?
+    /// Waits for the download; `output` then names where it actually landed.
+    pub async fn join(&mut self) -> Result<Outcome, DownloadError> {
+        let result = match self.handle.take().ok_or(DownloadError::Cancelled)?.await {
+            Err(e) if e.is_cancelled() => Err(DownloadError::Cancelled),
+            result => result?,
+        };
+        if let Some(output) = self
+            .placed
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+        {
+            self.output = output;
+        }
+        result
+    }
+
+    /// Stops the download like `shutdown`, so the writer drains and closes
+    /// the part file, aborting it only if it does not stop in time.
+    pub async fn cancel(&mut self, delete: bool) -> Result<(), DownloadError> {
+        // A download that failed on its own while stopping still gets cleaned
+        // up; its error is reported afterwards
+        let mut outcome = Ok(());
+        if let Some(mut handle) = self.handle.take() {
+            self.stop.store(true, Ordering::Relaxed);
+            self.pause.send_replace(false);
+            let result = match tokio::time::timeout(CANCEL_GRACE, &mut handle).await {
+                Ok(result) => result,
+                Err(_) => {
+                    handle.abort();
+                    handle.await
+                }
+            };
+            match result {
+                Err(e) if e.is_cancelled() => self.tracer.set_status(Status::Cancelled),
+                Ok(Err(DownloadError::Cancelled)) => self.tracer.set_status(Status::Cancelled),
+                Ok(result) => outcome = result.map(drop),
+                Err(e) => outcome = Err(e.into()),
+            }
+        }
+        if delete || self.config.on_failure == OnFailure::Delete {
+            match tokio::fs::remove_file(self.part_path(&self.output)).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e)?,
+                _ => {}
+            }
+            tokio::fs::remove_file(self.state_path(&self.output))
+                .await
+                .ok();
+        }
+        outcome
+    }
+
+    /// Resolves `output` inside the configured sandbox, if any, and checks
+    /// it against the policy.
+    fn confine(&self, output: &str) -> Result<String, DownloadError> {
+        let output = match &self.config.sandbox {
+            Some(sandbox) => sandbox.check(output)?.to_string_lossy().into_owned(),
+            None => output.to_owned(),
+        };
+        if let Some(policy) = &self.config.policy {
+            policy.check(&self.final_url, &output)?;
+        }
+        Ok(output)
+    }
+
+    pub fn category(&self) -> Category {
+        let mime = self
+            .headers
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok());
+        Category::detect(&self.output, mime)
+    }
+
+    pub fn vars(&self) -> Vars {
+        Vars::for_download(&self.url, &self.output)
+            .with("final_url", &self.final_url)
+            .with("category", self.category().as_str())
+            .with("size", Format::from_env().bytes(self.state.total_size))
+            .with(
+                "sha256",
+                self.sha256
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .clone()
+                    .unwrap_or_default(),
+            )
+    }
+}
+
+async fn within<F: Future>(timeout: Option<Duration>, future: F) -> Option<F::Output> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future).await.ok(),
+        None => Some(future.await),
     }
 }
 
-async fn download(
+/// The probe's headers as they describe the whole file. A 206 answers for
+/// the probed range only, so its length, Content-Range and Content-MD5 go.
+fn representation(response: &reqwest::Response, total_size: u64) -> HeaderMap {
+    let mut headers = response.headers().clone();
+    if response.status() == StatusCode::PARTIAL_CONTENT {
+        headers.remove(CONTENT_RANGE);
+        headers.remove("content-md5");
+        headers.remove("content-digest");
+        headers.remove(CONTENT_LENGTH);
+        if total_size > 0 {
+            headers.insert(CONTENT_LENGTH, total_size.into());
+        }
+    }
+    headers
+}
+
+fn header_u64(response: &reqwest::Response, name: HeaderName) -> u64 {
+    response
+        .headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_default()
+}
+
+fn content_range(response: &reqwest::Response) -> Option<(u64, u64, Option<u64>)> {
+    let value = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let (range, total) = value.strip_prefix("bytes ")?.split_once('/')?;
+    let (first, last) = range.split_once('-')?;
+    Some((first.parse().ok()?, last.parse().ok()?, total.parse().ok()))
+}
+
+/// Renames the finished part file to `output`, or to a free name if
+/// something took `output` while downloading. Artifacts on another
+/// filesystem are copied beside `output` first, so the final step is still
+/// an atomic rename.
+async fn place(
+    part: &std::path::Path,
+    output: &str,
+    collision: Collision,
+) -> Result<String, DownloadError> {
+    let output = match (tokio::fs::try_exists(output).await?, collision) {
+        (false, _) | (true, Collision::Overwrite) => output.to_owned(),
+        (true, Collision::Rename) => unique(output),
+        (true, Collision::Skip) => return Err(DownloadError::AlreadyExists(output.to_owned())),
+    };
+    move_part(part, &output).await?;
+    Ok(output)
+}
+
+async fn move_part(part: &std::path::Path, output: &str) -> std::io::Result<()> {
+    match tokio::fs::rename(part, output).await {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            let staged = format!("{}.part", output);
+            tokio::fs::copy(part, &staged).await?;
+            tokio::fs::rename(&staged, output).await?;
+            tokio::fs::remove_file(part).await
+        }
+        result => result,
+    }
+}
+
+fn chunk_range(i: u64, chunk_size: u64, total_size: u64) -> (u64, u64) {
+    (i * chunk_size, ((i + 1) * chunk_size).min(total_size) - 1)
+}
+
+/// A permit under the ceiling shared with other downloads, if there is one.
+async fn shared_slot(connections: Option<&Gate>) -> Option<SemaphorePermit<'_>> {
+    Some(connections?.acquire().await)
+}
+
+struct Mirrors {
+    urls: Vec<String>,
+    dead: Vec<AtomicBool>,
+    gates: Vec<Option<Arc<Gate>>>,
+}
+
+impl Mirrors {
+    fn new(urls: Vec<String>, host_limit: Option<usize>) -> Self {
+        let dead = urls.iter().map(|_| AtomicBool::new(false)).collect();
+        let mut hosts: HashMap<String, Arc<Gate>> = HashMap::new();
+        let gates = urls
+            .iter()
+            .map(|url| {
+                let limit = host_limit?;
+                let host = reqwest::Url::parse(url).ok()?.host_str()?.to_owned();
+                let gate = hosts
+                    .entry(host)
+                    .or_insert_with(|| Arc::new(Gate::new(limit)));
+                Some(gate.clone())
+            })
+            .collect();
+        Self { urls, dead, gates }
+    }
+
+    async fn slot(&self, mirror: usize) -> Option<SemaphorePermit<'_>> {
+        Some(self.gates[mirror].as_ref()?.acquire().await)
+    }
+
+    fn pick(&self, turn: usize) -> Option<usize> {
+        let count = self.urls.len();
+        (0..count)
+            .map(|k| (turn + k) % count)
+            .find(|&m| !self.dead[m].load(Ordering::Relaxed))
+    }
+
+    fn fail(&self, mirror: usize) -> bool {
+        let others =
+            (0..self.urls.len()).any(|m| m != mirror && !self.dead[m].load(Ordering::Relaxed));
+        if others {
+            self.dead[mirror].store(true, Ordering::Relaxed);
+        }
+        others
+    }
+}
+
+struct Job {
     client: Client,
-    url: String,
+    mirrors: Mirrors,
     output: String,
+    part: PathBuf,
+    collision: Collision,
+    placed: Arc<Mutex<Option<String>>>,
+    sha256: Arc<Mutex<Option<String>>>,
+    prefetched: Option<reqwest::Response>,
     total_chunk: u64,
+    chunk_size: u64,
+    gate: Arc<Gate>,
+    connections: Option<Arc<Gate>>,
+    order: ChunkOrder,
+    stall_timeout: Option<Duration>,
+    retries: usize,
+    no_index: bool,
+    zone: Option<String>,
+    compressed: bool,
+    mmap: bool,
+    quota: Option<Quota>,
+    sandbox: Option<Sandbox>,
     tracer: Tracer,
-) -> Result<(), DownloadError> {
-    let total_size = tracer.total_size;
-    File::create(&output).await?.set_len(total_size).await?;
-
-    let producers = iter((0..total_chunk).map(|i| {
-        let client = client.clone();
-        let url = &url;
-        let output = &output;
-        let tracer = &tracer;
-        async move {
-            let start = i * MB;
-            let end = (i == total_chunk - 1)
-                .then_some(total_size)
-                .unwrap_or((i + 1) * MB - 1);
-            let response = client
-                .get(url)
-                .header("Range", format!("bytes={}-{}", start, end))
-                .send()
-                .await?;
-            if response.status().is_success() {
-                let mut file = BufWriter::new(OpenOptions::new().write(true).open(output).await?);
-                file.seek(std::io::SeekFrom::Start(start)).await?;
+    pause: Receiver<bool>,
+    stop: Arc<AtomicBool>,
+    limiter: Arc<Limiter>,
+    connection_rate: u64,
+    journal: Journal,
+    resumed: bool,
+    checksum: Option<Checksum>,
+    chunk_headers: Arc<OnceLock<HeaderMap>>,
+    trace: Arc<Trace>,
+    chunks: Arc<ChunkMap>,
+    partial: bool,
+    auto_tune: bool,
+}
+
+struct Fetcher<'a> {
+    client: &'a Client,
+    mirrors: &'a Mirrors,
+    writer: &'a Writer,
+    tracer: &'a Tracer,
+    journal: &'a Journal,
+    limiter: &'a Limiter,
+    connection_rate: u64,
+    chunk_headers: &'a OnceLock<HeaderMap>,
+    trace: &'a Trace,
+    chunks: &'a ChunkMap,
+    validator: Option<&'a str>,
+    changed: &'a AtomicBool,
+    ignored: &'a AtomicBool,
+    pause: &'a Receiver<bool>,
+    stop: &'a AtomicBool,
+    board: &'a Board,
+    digest: &'a Incremental,
+    chunk_size: u64,
+    stall_timeout: Option<Duration>,
+    total_size: u64,
+}
+
+impl Fetcher<'_> {
+    async fn run(&self, part: Arc<Part>) -> Result<(), DownloadError> {
+        let chunk = part.chunk;
+        let result = self.fetch(part).await;
+        if let Err(e) = &result
+            && !matches!(e, DownloadError::Cancelled)
+        {
+            self.chunks.fail(chunk, e.to_string());
+        }
+        result
+    }
+
+    async fn write(&self, offset: u64, data: Vec<u8>) -> Result<(), DownloadError> {
+        self.digest.feed(offset, &data);
+        self.writer.write(offset, data).await?;
+        Ok(())
+    }
+
+    async fn fetch(&self, part: Arc<Part>) -> Result<(), DownloadError> {
+        let i = part.chunk;
+        let mut pause = self.pause.clone();
+        pause.wait_for(|p| !p).await.ok();
+        if self.stop.load(Ordering::Relaxed) {
+            return Err(DownloadError::Cancelled);
+        }
+        let mut sample = Vec::with_capacity(SAMPLE);
+        let mut buffer = Vec::with_capacity(WRITE_BUFFER);
+        let mut offset = part.span().0;
+        let mut stalls = 0;
+        let mut turn = i as usize;
+        let mut tail = Vec::with_capacity(OVERLAP);
+        let mut source = None;
+        loop {
+            let (from, end) = part.span();
+            if from > end {
+                break;
+            }
+            let mirror = self.mirrors.pick(turn).ok_or(DownloadError::Stalled(i))?;
+            let overlap = match source {
+                Some(source) if source != mirror => tail.clone(),
+                _ => Vec::new(),
+            };
+            let start = from - overlap.len() as u64;
+            let _slot = self.mirrors.slot(mirror).await;
+            let connection = Limiter::new(self.connection_rate);
+            let attempt = async {
+                let mut request = self
+                    .client
+                    .get(&self.mirrors.urls[mirror])
+                    .header("Range", format!("bytes={}-{}", start, end))
+                    .header(ACCEPT_ENCODING, "identity");
+                if let Some(validator) = self.validator {
+                    request = request.header("If-Range", validator);
+                }
+                let label = format!("chunk {}", i);
+                let sent = self.trace.send(self.client, &label, request);
+                let Some(response) = within(self.stall_timeout, sent).await else {
+                    return Ok(false);
+                };
+                let response = response?;
+                let whole = (start, end) == (0, self.total_size - 1);
+                if response.status() == StatusCode::OK && !whole {
+                    return Err(match self.validator {
+                        Some(_) => {
+                            self.changed.store(true, Ordering::Relaxed);
+                            DownloadError::RemoteChanged(i)
+                        }
+                        None => {
+                            self.ignored.store(true, Ordering::Relaxed);
+                            DownloadError::RangeIgnored(i)
+                        }
+                    });
+                }
+                if !response.status().is_success() {
+                    return Err(DownloadError::ChunkStatus(i, response.status().to_string()));
+                }
+                if let Some(encoding) = response
+                    .headers()
+                    .get(CONTENT_ENCODING)
+                    .filter(|e| !e.as_bytes().eq_ignore_ascii_case(b"identity"))
+                {
+                    let encoding = String::from_utf8_lossy(encoding.as_bytes());
+                    return Err(DownloadError::ChunkStatus(
+                        i,
+                        format!("encoded as {}", encoding),
+                    ));
+                }
+                if response.status() == StatusCode::PARTIAL_CONTENT {
+                    let range = content_range(&response);
+                    if range.is_none_or(|(first, last, total)| {
+                        (first, last) != (start, end) || total.is_some_and(|t| t != self.total_size)
+                    }) {
+                        return Err(DownloadError::ChunkStatus(
+                            i,
+                            format!("Content-Range {:?} for bytes {}-{}", range, start, end),
+                        ));
+                    }
+                } else if !whole {
+                    return Err(DownloadError::ChunkStatus(i, response.status().to_string()));
+                }
+                let length = header_u64(&response, CONTENT_LENGTH);
+                if response.headers().contains_key(CONTENT_LENGTH) && length != end - start + 1 {
+                    return Err(DownloadError::ChunkStatus(
+                        i,
+                        format!("sent {} bytes for a {} byte range", length, end - start + 1),
+                    ));
+                }
+                self.chunk_headers
+                    .get_or_init(|| response.headers().clone());
                 let mut stream = response.bytes_stream();
-                while let Some(chunk) = stream.next().await {
+                let mut checked = 0;
+                loop {
+                    let Some(chunk) = within(self.stall_timeout, stream.next()).await else {
+                        return Ok(false);
+                    };
+                    let Some(chunk) = chunk else {
+                        return Ok(part.done());
+                    };
                     let chunk = chunk?;
-                    tracer.add(chunk.len() as u64);
-                    file.write_all(&chunk).await?;
+                    let mut chunk = &chunk[..];
+                    if checked < overlap.len() {
+                        let len = (overlap.len() - checked).min(chunk.len());
+                        if chunk[..len] != overlap[checked..checked + len] {
+                            let url = self.mirrors.urls[mirror].clone();
+                            return Err(DownloadError::MirrorContentMismatch(url));
+                        }
+                        checked += len;
+                        chunk = &chunk[len..];
+                    }
+                    let chunk = &chunk[..part.advance(chunk.len() as u64) as usize];
+                    source = Some(mirror);
+                    tail.extend_from_slice(&chunk[chunk.len().saturating_sub(OVERLAP)..]);
+                    tail.drain(..tail.len().saturating_sub(OVERLAP));
+                    if part.owner {
+                        let needed = SAMPLE - sample.len();
+                        sample.extend_from_slice(&chunk[..needed.min(chunk.len())]);
+                    }
+                    self.tracer.add(chunk.len() as u64);
+                    self.chunks.add(i, chunk.len() as u64);
+                    buffer.extend_from_slice(chunk);
+                    if buffer.len() >= WRITE_BUFFER {
+                        let data = std::mem::replace(&mut buffer, Vec::with_capacity(WRITE_BUFFER));
+                        let len = data.len() as u64;
+                        self.write(offset, data).await?;
+                        offset += len;
+                    }
+                    self.limiter.acquire(chunk.len() as u64).await;
+                    connection.acquire(chunk.len() as u64).await;
+                    pause.wait_for(|p| !p).await.ok();
+                    if self.stop.load(Ordering::Relaxed) {
+                        return Err(DownloadError::Cancelled);
+                    }
+                    if part.done() {
+                        return Ok(true);
+                    }
                 }
-                file.flush().await?;
-                Ok(())
-            } else {
-                Err(DownloadError::ChunkStatus(i, response.status().to_string()))
+            };
+            match attempt.await {
+                Ok(true) => break,
+                Ok(false) => {
+                    stalls += 1;
+                    turn += 1;
+                    self.chunks.retry(i);
+                    self.tracer.sink.on_chunk_retry(i);
+                    if stalls > STALL_RETRIES {
+                        return Err(DownloadError::Stalled(i));
+                    }
+                }
+                Err(e @ (DownloadError::HttpRequest(_) | DownloadError::ChunkStatus(..)))
+                    if self.mirrors.fail(mirror) =>
+                {
+                    self.tracer
+                        .sink
+                        .on_mirror_failed(&self.mirrors.urls[mirror], &e);
+                    turn += 1;
+                    self.chunks.retry(i);
+                }
+                Err(e) => return Err(e),
             }
         }
-    }))
-    .buffer_unordered(32);
+        self.write(offset, buffer).await?;
+        let sample = part.owner.then(|| fingerprint(&sample));
+        if let Some(sample) = self.board.finish(&part, sample) {
+            self.writer.barrier().await?;
+            self.journal.complete(i, sample)?;
+            self.chunks.done(i);
+            let prefix = self.journal.prefix() * self.chunk_size;
+            self.digest.catch_up(prefix.min(self.total_size)).await?;
+            self.tracer.sink.on_chunk_complete(i);
+        }
+        Ok(())
+    }
+}
 
-    let error: String = producers
-        .collect::<Vec<_>>()
-        .await
-        .into_iter()
-        .filter_map(|r| r.err().map(|e| e.to_string()))
-        .collect();
+async fn download(job: Job) -> Result<Outcome, DownloadError> {
+    // `output` is a public field, so check it again before anything is written
+    if let Some(sandbox) = &job.sandbox {
+        sandbox.check(&job.output)?;
+    }
+    if job.tracer.total_size <= SMALL_FILE || job.compressed {
+        return stream(job).await.map(|()| Outcome::Complete);
+    }
+    let Job {
+        client,
+        mirrors,
+        output,
+        part,
+        collision,
+        placed,
+        sha256,
+        mut total_chunk,
+        mut chunk_size,
+        gate,
+        connections,
+        order,
+        stall_timeout,
+        retries,
+        no_index,
+        zone,
+        tracer,
+        pause,
+        stop,
+        limiter,
+        connection_rate,
+        journal,
+        resumed,
+        checksum,
+        chunk_headers,
+        trace,
+        chunks,
+        quota,
+        mmap,
+        partial,
+        auto_tune,
+        ..
+    } = job;
+    let total_size = tracer.total_size;
+    if !resumed {
+        reserve(&part, total_size, quota.as_ref(), &tracer).await?;
+        let path = part.clone();
+        tokio::task::spawn_blocking(move || prealloc::preallocate(path.as_ref(), total_size))
+            .await??;
+    }
+    attrs::mark_in_progress(part.as_ref(), no_index);
+    let output_file = match mmap {
+        true => Output::map(&part).or_else(|_| Output::open(&part))?,
+        false => Output::open(&part)?,
+    };
+    let (writer, writing) = Writer::spawn(output_file);
+    let digest = Incremental::new(checksum, &part);
+    let tuner = (auto_tune && total_chunk > 1 && connection_rate == 0).then(|| {
+        let host = reqwest::Url::parse(&mirrors.urls[0])
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_owned))
+            .unwrap_or_default();
+        tokio::spawn(tune::tune(
+            host,
+            gate.clone(),
+            limiter.clone(),
+            tracer.clone(),
+            pause.clone(),
+        ))
+    });
 
-    error
-        .is_empty()
-        .then_some(())
-        .ok_or(DownloadError::ChunkFailure(error))
+    let validator = match mirrors.urls.len() {
+        1 => journal.validator(),
+        _ => None,
+    };
+    let changed = AtomicBool::new(false);
+    let ignored = AtomicBool::new(false);
+    let mut restarted = false;
+    let mut attempts = 0;
+    let (error, salvageable) = loop {
+        let board = Board::default();
+        let fetcher = Fetcher {
+            client: &client,
+            mirrors: &mirrors,
+            writer: &writer,
+            tracer: &tracer,
+            journal: &journal,
+            limiter: &limiter,
+            connection_rate,
+            chunk_headers: &chunk_headers,
+            trace: &trace,
+            chunks: &chunks,
+            validator: validator.as_deref(),
+            changed: &changed,
+            ignored: &ignored,
+            pause: &pause,
+            stop: &stop,
+            board: &board,
+            digest: &digest,
+            chunk_size,
+            stall_timeout,
+            total_size,
+        };
+        let pending = (0..total_chunk).filter(|&i| !journal.is_done(i)).collect();
+        let work = order.arrange(pending).into_iter().map(Some);
+        let stealers = (0..MAX_PARALLEL).filter(|_| total_chunk > 1).map(|_| None);
+        let producers = iter(work.chain(stealers).map(|chunk| {
+            let (fetcher, gate, connections) = (&fetcher, &gate, connections.as_deref());
+            async move {
+                match chunk {
+                    Some(i) => {
+                        let _permit = gate.acquire().await;
+                        let _shared = shared_slot(connections).await;
+                        if fetcher.stop.load(Ordering::Relaxed) {
+                            return Ok(());
+                        }
+                        let (start, end) = chunk_range(i, chunk_size, total_size);
+                        fetcher.run(fetcher.board.register(i, start, end)).await
+                    }
+                    None => loop {
+                        let _permit = gate.acquire().await;
+                        let _shared = shared_slot(connections).await;
+                        if fetcher.stop.load(Ordering::Relaxed) {
+                            return Ok(());
+                        }
+                        let Some(part) = fetcher.board.steal(MIN_STEAL) else {
+                            return Ok(());
+                        };
+                        fetcher.run(part).await?;
+                    },
+                }
+            }
+        }))
+        .buffer_unordered(MAX_PARALLEL);
+
+        let results = producers.collect::<Vec<_>>().await;
+        let mismatch = results
+            .iter()
+            .any(|r| matches!(r, Err(DownloadError::MirrorContentMismatch(_))));
+        let error: String = results
+            .into_iter()
+            .filter_map(|r| r.err().map(|e| e.to_string()))
+            .collect();
+        if stop.load(Ordering::Relaxed) {
+            break (error, false);
+        }
+        if ignored.swap(false, Ordering::Relaxed) && total_chunk > 1 {
+            (total_chunk, chunk_size) = (1, total_size);
+            journal.reset();
+            tracer.reset(0);
+            chunks.reset(total_chunk, |_| false);
+            digest.reset();
+            continue;
+        }
+        let stale = changed.swap(false, Ordering::Relaxed);
+        if stale && !restarted {
+            restarted = true;
+            journal.reset();
+            tracer.reset(0);
+            chunks.reset(total_chunk, |_| false);
+            digest.reset();
+            continue;
+        }
+        if stale || mismatch || error.is_empty() || attempts >= retries {
+            break (error, !stale && !mismatch);
+        }
+        attempts += 1;
+        writer.barrier().await?;
+        journal.flush()?;
+        tracer.reset(
+            (0..total_chunk)
+                .filter(|&i| journal.is_done(i))
+                .map(|i| {
+                    let (start, end) = chunk_range(i, chunk_size, total_size);
+                    end - start + 1
+                })
+                .sum(),
+        );
+        tokio::time::sleep(RETRY_BACKOFF * (1 << (attempts - 1).min(5))).await;
+    };
+
+    if let Some(tuner) = tuner {
+        tuner.abort();
+    }
+    drop(writer);
+    let written = writing.await.map_err(std::io::Error::other)?;
+    let stopped = stop.load(Ordering::Relaxed);
+    if stopped || !error.is_empty() || written.is_err() {
+        if total_chunk > 1 {
+            journal.flush()?;
+        }
+        written?;
+        if partial && salvageable && !stopped && total_chunk > 1 {
+            let missing_ranges = (0..total_chunk)
+                .filter(|&i| !journal.is_done(i))
+                .map(|i| chunk_range(i, chunk_size, total_size))
+                .fold(Vec::<(u64, u64)>::new(), |mut ranges, (start, end)| {
+                    match ranges.last_mut() {
+                        Some(last) if last.1 + 1 == start => last.1 = end,
+                        _ => ranges.push((start, end)),
+                    }
+                    ranges
+                });
+            return Ok(Outcome::PartialSuccess { missing_ranges });
+        }
+        return Err(match stopped {
+            true => DownloadError::Cancelled,
+            false => DownloadError::ChunkFailure(error),
+        });
+    }
+    let digest = digest.finish(total_size).await?;
+    let output = place(&part, &output, collision).await?;
+    *placed.lock().unwrap_or_else(PoisonError::into_inner) = Some(output.clone());
+    *sha256.lock().unwrap_or_else(PoisonError::into_inner) = Some(digest);
+    journal.remove();
+    attrs::mark_finished(output.as_ref());
+    if let Some(zone) = &zone {
+        attrs::mark_of_the_web(output.as_ref(), zone);
+    }
+    Ok(Outcome::Complete)
+}
+
+/// Waits for room under the quota and makes sure the disk holding `part`
+/// has `size` bytes free.
+async fn reserve(
+    part: &std::path::Path,
+    size: u64,
+    quota: Option<&Quota>,
+    tracer: &Tracer,
+) -> Result<(), DownloadError> {
+    if let Some(quota) = quota {
+        quota.wait(size, tracer).await?;
+    }
+    let dir = part
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(".".as_ref());
+    tokio::fs::create_dir_all(dir).await?;
+    let available = fs2::available_space(dir)?;
+    if available < size {
+        return Err(DownloadError::InsufficientSpace {
+            needed: size,
+            available,
+        });
+    }
+    Ok(())
+}
+
+/// Single-request path for small and compressed bodies, started over from
+/// scratch when the connection fails.
+async fn stream(mut job: Job) -> Result<(), DownloadError> {
+    reserve(
+        &job.part,
+        job.tracer.total_size,
+        job.quota.as_ref(),
+        &job.tracer,
+    )
+    .await?;
+    let mut attempts = 0;
+    loop {
+        match stream_once(&mut job).await {
+            Err(DownloadError::HttpRequest(e))
+                if attempts < job.retries
+                    && !job.stop.load(Ordering::Relaxed)
+                    && !e.status().is_some_and(|s| s.is_client_error()) =>
+            {
+                attempts += 1;
+                tokio::time::sleep(RETRY_BACKOFF * (1 << (attempts - 1).min(5))).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn stream_once(job: &mut Job) -> Result<(), DownloadError> {
+    let (tracer, chunks) = (&job.tracer, &job.chunks);
+    tracer.reset(0);
+    chunks.reset(1, |_| false);
+    File::create(&job.part).await?;
+    attrs::mark_in_progress(job.part.as_ref(), job.no_index);
+    let (writer, writing) = Writer::spawn(Output::open(&job.part)?);
+    let connection = Limiter::new(job.connection_rate);
+    let response = match job.prefetched.take() {
+        Some(response) => response,
+        None => job
+            .trace
+            .send(&job.client, "stream", job.client.get(&job.mirrors.urls[0]))
+            .await?
+            .error_for_status()?,
+    };
+    job.chunk_headers.get_or_init(|| response.headers().clone());
+    let mut stream = response.bytes_stream();
+    let mut buffer = Vec::with_capacity(WRITE_BUFFER);
+    let mut offset = 0;
+    let digest = Incremental::new(job.checksum.clone(), &job.part);
+    let mut hashed = 0;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        digest.feed(hashed, &chunk);
+        hashed += chunk.len() as u64;
+        tracer.add(chunk.len() as u64);
+        chunks.add(0, chunk.len() as u64);
+        buffer.extend_from_slice(&chunk);
+        if buffer.len() >= WRITE_BUFFER {
+            let data = std::mem::replace(&mut buffer, Vec::with_capacity(WRITE_BUFFER));
+            let len = data.len() as u64;
+            writer.write(offset, data).await?;
+            offset += len;
+        }
+        job.limiter.acquire(chunk.len() as u64).await;
+        connection.acquire(chunk.len() as u64).await;
+        job.pause.wait_for(|p| !p).await.ok();
+        if job.stop.load(Ordering::Relaxed) {
+            break;
+        }
+    }
+    writer.write(offset, buffer).await?;
+    drop(writer);
+    writing.await.map_err(std::io::Error::other)??;
+    if job.stop.load(Ordering::Relaxed) {
+        return Err(DownloadError::Cancelled);
+    }
+    chunks.done(0);
+    let digest = digest.finish(hashed).await?;
+    let output = place(&job.part, &job.output, job.collision).await?;
+    *job.placed.lock().unwrap_or_else(PoisonError::into_inner) = Some(output.clone());
+    *job.sha256.lock().unwrap_or_else(PoisonError::into_inner) = Some(digest);
+    attrs::mark_finished(output.as_ref());
+    if let Some(zone) = &job.zone {
+        attrs::mark_of_the_web(output.as_ref(), zone);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn place_settles_files_that_appeared_meanwhile() {
+        let dir = std::env::temp_dir().join(format!("stupidl-place-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("file.bin").to_string_lossy().into_owned();
+        let part = dir.join("file.bin.part");
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let place = |collision| {
+            std::fs::write(&output, b"theirs").unwrap();
+            std::fs::write(&part, b"ours").unwrap();
+            runtime.block_on(place(&part, &output, collision))
+        };
+        assert!(matches!(
+            place(Collision::Skip),
+            Err(DownloadError::AlreadyExists(_))
+        ));
+        assert_eq!(std::fs::read(&output).unwrap(), b"theirs");
+        let renamed = place(Collision::Rename).unwrap();
+        assert_ne!(renamed, output);
+        assert_eq!(std::fs::read(&output).unwrap(), b"theirs");
+        assert_eq!(std::fs::read(&renamed).unwrap(), b"ours");
+        assert_eq!(place(Collision::Overwrite).unwrap(), output);
+        assert_eq!(std::fs::read(&output).unwrap(), b"ours");
+        std::fs::remove_dir_all(dir).ok();
+    }
 }
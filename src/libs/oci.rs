@@ -0,0 +1,98 @@
+use super::{DownloadError, Resolved};
+use reqwest::{Client, StatusCode, header::WWW_AUTHENTICATE};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug)]
+pub struct Blob {
+    pub registry: String,
+    pub repository: String,
+    pub digest: String,
+}
+
+#[derive(Deserialize)]
+struct Token {
+    #[serde(alias = "access_token")]
+    token: String,
+}
+
+impl Blob {
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (path, digest) = spec.strip_prefix("docker://")?.split_once('@')?;
+        digest.strip_prefix("sha256:")?;
+        let (registry, repository) = match path.split_once('/') {
+            Some((host, repo)) if host.contains(['.', ':']) || host == "localhost" => {
+                (host.to_owned(), repo.to_owned())
+            }
+            _ => ("registry-1.docker.io".to_owned(), path.to_owned()),
+        };
+        let registry = match registry.as_str() {
+            "docker.io" | "index.docker.io" => "registry-1.docker.io".to_owned(),
+            _ => registry,
+        };
+        let repository = match repository.contains('/') {
+            false if registry == "registry-1.docker.io" => format!("library/{}", repository),
+            _ => repository,
+        };
+        Some(Self {
+            registry,
+            repository,
+            digest: digest.to_owned(),
+        })
+    }
+
+    pub fn url(&self) -> String {
+        format!(
+            "https://{}/v2/{}/blobs/{}",
+            self.registry, self.repository, self.digest
+        )
+    }
+
+    /// Fetches a pull token over `client`, normally `DownloaderConfig::client`.
+    pub async fn resolve(&self, client: &Client) -> Result<Resolved, DownloadError> {
+        let url = self.url();
+        let response = client.head(&url).send().await?;
+        let mut headers = HashMap::new();
+        if response.status() == StatusCode::UNAUTHORIZED {
+            let challenge = response
+                .headers()
+                .get(WWW_AUTHENTICATE)
+                .and_then(|v| v.to_str().ok())
+                .ok_or_else(|| DownloadError::Registry("missing auth challenge".to_owned()))?;
+            let token = self.token(client, challenge).await?;
+            headers.insert("Authorization".to_owned(), format!("Bearer {}", token));
+        }
+        Ok(Resolved {
+            urls: vec![url],
+            headers,
+            checksum: Some(self.digest.clone()),
+        })
+    }
+
+    async fn token(&self, client: &Client, challenge: &str) -> Result<String, DownloadError> {
+        let params: HashMap<_, _> = challenge
+            .strip_prefix("Bearer ")
+            .unwrap_or(challenge)
+            .split(',')
+            .filter_map(|p| p.trim().split_once('='))
+            .map(|(k, v)| (k.to_ascii_lowercase(), v.trim_matches('"')))
+            .collect();
+        let realm = params
+            .get("realm")
+            .ok_or_else(|| DownloadError::Registry(format!("bad challenge: {}", challenge)))?;
+        let scope = format!("repository:{}:pull", self.repository);
+        let mut query = vec![("scope", scope.as_str())];
+        if let Some(service) = params.get("service") {
+            query.push(("service", service));
+        }
+        let token: Token = client
+            .get(*realm)
+            .query(&query)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        Ok(token.token)
+    }
+}
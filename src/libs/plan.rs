@@ -0,0 +1,36 @@
+use super::ChunkOrder;
+use std::fmt;
+
+#[derive(Clone, Debug)]
+pub struct Plan {
+    pub mirrors: Vec<String>,
+    pub final_url: String,
+    pub output: String,
+    pub total_size: u64,
+    pub chunk_size: u64,
+    pub total_chunk: u64,
+    pub done_chunk: u64,
+    pub max_parallel: usize,
+    pub order: ChunkOrder,
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for mirror in &self.mirrors {
+            writeln!(f, "Mirror:      {}", mirror)?;
+        }
+        writeln!(f, "Final URL:   {}", self.final_url)?;
+        writeln!(f, "Output:      {}", self.output)?;
+        match self.total_size {
+            0 => writeln!(f, "Size:        unknown, single stream")?,
+            size => writeln!(f, "Size:        {} bytes", size)?,
+        }
+        writeln!(
+            f,
+            "Chunks:      {} x {} bytes ({} already done)",
+            self.total_chunk, self.chunk_size, self.done_chunk
+        )?;
+        writeln!(f, "Concurrency: {}", self.max_parallel)?;
+        write!(f, "Order:       {:?}", self.order)
+    }
+}
@@ -0,0 +1,110 @@
+use super::DownloadError;
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+const PLUGIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Serialize)]
+struct Request<'a> {
+    url: &'a str,
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct Resolved {
+    pub urls: Vec<String>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub checksum: Option<String>,
+}
+
+impl Resolved {
+    pub fn header_map(&self) -> Result<HeaderMap, DownloadError> {
+        HeaderMap::try_from(&self.headers).map_err(|e| DownloadError::Plugin(e.to_string()))
+    }
+}
+
+pub struct Plugin {
+    pub path: PathBuf,
+}
+
+impl Plugin {
+    pub fn discover(dir: impl AsRef<Path>) -> Vec<Self> {
+        let mut plugins: Vec<_> = std::fs::read_dir(dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| is_executable(p))
+            .map(|path| Self { path })
+            .collect();
+        plugins.sort_by(|a, b| a.path.cmp(&b.path));
+        plugins
+    }
+
+    pub async fn resolve(&self, url: &str) -> Result<Option<Resolved>, DownloadError> {
+        let mut child = Command::new(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        let request = serde_json::to_vec(&Request { url })
+            .map_err(|e| DownloadError::Plugin(e.to_string()))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&request).await?;
+        }
+        let output = tokio::time::timeout(PLUGIN_TIMEOUT, child.wait_with_output())
+            .await
+            .map_err(|_| DownloadError::Plugin(format!("{} timed out", self.path.display())))??;
+        if !output.status.success() || output.stdout.trim_ascii().is_empty() {
+            return Ok(None);
+        }
+        let resolved: Resolved = serde_json::from_slice(&output.stdout)
+            .map_err(|e| DownloadError::Plugin(format!("{}: {}", self.path.display(), e)))?;
+        Ok((!resolved.urls.is_empty()).then_some(resolved))
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    path.is_file()
+        && ["exe", "com", "bat", "cmd"]
+            .iter()
+            .any(|e| extension.eq_ignore_ascii_case(e))
+}
+
+/// First plugin that claims `url`. A plugin that fails or hangs is skipped
+/// rather than blocking the download; if none claims `url`, the last
+/// failure is returned.
+pub async fn resolve(plugins: &[Plugin], url: &str) -> Result<Option<Resolved>, DownloadError> {
+    let mut failure = None;
+    for plugin in plugins {
+        match plugin.resolve(url).await {
+            Ok(Some(resolved)) => return Ok(Some(resolved)),
+            Ok(None) => {}
+            Err(e @ DownloadError::Plugin(_)) => failure = Some(e),
+            Err(e) => {
+                let message = format!("{}: {}", plugin.path.display(), e);
+                failure = Some(DownloadError::Plugin(message));
+            }
+        }
+    }
+    failure.map_or(Ok(None), Err)
+}
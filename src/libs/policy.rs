@@ -0,0 +1,112 @@
+use super::DownloadError;
+use reqwest::Url;
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct Policy {
+    pub allow_hosts: Vec<String>,
+    pub deny_hosts: Vec<String>,
+    pub deny_extensions: Vec<String>,
+    pub trusted_hosts: Vec<String>,
+}
+
+impl Policy {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DownloadError> {
+        serde_json::from_slice(&std::fs::read(path)?)
+            .map_err(|e| DownloadError::InvalidPolicy(e.to_string()))
+    }
+
+    pub fn check(&self, url: &str, filename: &str) -> Result<(), DownloadError> {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_ascii_lowercase))
+            .unwrap_or_default();
+        let violation = |reason: String| Err(DownloadError::PolicyViolation(reason));
+        if !self.allow_hosts.is_empty() && !matches_any(&self.allow_hosts, &host) {
+            return violation(format!("host {} is not allowed", host));
+        }
+        if matches_any(&self.deny_hosts, &host) {
+            return violation(format!("host {} is blocked", host));
+        }
+        let extension = Path::new(filename)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        if self
+            .deny_extensions
+            .iter()
+            .any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(extension))
+            && !matches_any(&self.trusted_hosts, &host)
+        {
+            return violation(format!(".{} files from {} are blocked", extension, host));
+        }
+        Ok(())
+    }
+}
+
+pub(super) fn matches_any(patterns: &[String], host: &str) -> bool {
+    patterns.iter().any(|p| {
+        let p = p.to_ascii_lowercase();
+        match p.strip_prefix("*.") {
+            Some(domain) => host == domain || host.ends_with(&format!(".{}", domain)),
+            None => p == "*" || p == host,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> Policy {
+        Policy {
+            deny_hosts: vec!["*.evil.com".to_owned()],
+            deny_extensions: vec![".exe".to_owned()],
+            trusted_hosts: vec!["cdn.example.com".to_owned()],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn denies_hosts_and_subdomains() {
+        let policy = policy();
+        assert!(policy.check("https://evil.com/a.zip", "a.zip").is_err());
+        assert!(policy.check("https://dl.evil.com/a.zip", "a.zip").is_err());
+        assert!(policy.check("https://notevil.com/a.zip", "a.zip").is_ok());
+    }
+
+    #[test]
+    fn denies_extensions_unless_trusted() {
+        let policy = policy();
+        assert!(policy.check("https://example.com/a", "setup.EXE").is_err());
+        assert!(
+            policy
+                .check("https://cdn.example.com/a", "setup.exe")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn allow_list_excludes_other_hosts() {
+        let policy = Policy {
+            allow_hosts: vec!["example.com".to_owned()],
+            ..Default::default()
+        };
+        assert!(policy.check("https://example.com/a", "a").is_ok());
+        assert!(matches!(
+            policy.check("https://other.com/a", "a"),
+            Err(DownloadError::PolicyViolation(_))
+        ));
+    }
+
+    #[test]
+    fn parse_errors_are_not_violations() {
+        let path = std::env::temp_dir().join("stupidl-policy-test.json");
+        std::fs::write(&path, "{ not json").unwrap();
+        let result = Policy::load(&path);
+        std::fs::remove_file(&path).ok();
+        assert!(matches!(result, Err(DownloadError::InvalidPolicy(_))));
+    }
+}
@@ -0,0 +1,59 @@
+use std::{fs::File, io, path::Path};
+
+/// Creates `path` at its final length without zero-filling it first, so
+/// multi-gigabyte targets are ready before the first byte arrives.
+pub fn preallocate(path: &Path, len: u64) -> io::Result<()> {
+    let file = File::create(path)?;
+    if !imp::allocate(&file, len) {
+        file.set_len(len)?;
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+mod imp {
+    use std::{
+        fs::File,
+        os::windows::io::AsRawHandle,
+        ptr::{null, null_mut},
+    };
+    use windows_sys::Win32::System::{IO::DeviceIoControl, Ioctl::FSCTL_SET_SPARSE};
+
+    pub fn allocate(file: &File, len: u64) -> bool {
+        let mut returned = 0;
+        let sparse = unsafe {
+            DeviceIoControl(
+                file.as_raw_handle(),
+                FSCTL_SET_SPARSE,
+                null(),
+                0,
+                null_mut(),
+                0,
+                &mut returned,
+                null_mut(),
+            )
+        };
+        sparse != 0 && file.set_len(len).is_ok()
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod imp {
+    use std::{fs::File, os::fd::AsRawFd};
+
+    pub fn allocate(file: &File, len: u64) -> bool {
+        let Ok(len) = libc::off_t::try_from(len) else {
+            return false;
+        };
+        unsafe { libc::fallocate(file.as_raw_fd(), 0, 0, len) == 0 }
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+mod imp {
+    use std::fs::File;
+
+    pub fn allocate(_: &File, _: u64) -> bool {
+        false
+    }
+}
@@ -0,0 +1,80 @@
+use super::{DownloadError, Status, Tracer};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug)]
+pub struct Quota {
+    pub dir: PathBuf,
+    pub max: u64,
+}
+
+impl Quota {
+    pub fn new(dir: impl Into<PathBuf>, max: u64) -> Self {
+        Self {
+            dir: dir.into(),
+            max,
+        }
+    }
+
+    pub fn covers(&self, path: &Path) -> bool {
+        let parent = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        match (parent.canonicalize(), self.dir.canonicalize()) {
+            (Ok(parent), Ok(dir)) => parent.starts_with(dir),
+            _ => parent.starts_with(&self.dir),
+        }
+    }
+
+    pub fn usage(&self) -> u64 {
+        fn walk(dir: &Path) -> u64 {
+            let Ok(entries) = std::fs::read_dir(dir) else {
+                return 0;
+            };
+            entries
+                .flatten()
+                .map(|entry| match entry.file_type() {
+                    Ok(t) if t.is_dir() => walk(&entry.path()),
+                    Ok(t) if t.is_file() => entry.metadata().map_or(0, |m| m.len()),
+                    _ => 0,
+                })
+                .sum()
+        }
+        walk(&self.dir)
+    }
+
+    pub(super) async fn wait(&self, needed: u64, tracer: &Tracer) -> Result<(), DownloadError> {
+        if needed > self.max {
+            return Err(DownloadError::InsufficientSpace {
+                needed,
+                available: self.max,
+            });
+        }
+        let mut waiting = false;
+        loop {
+            let quota = self.clone();
+            let used = tokio::task::spawn_blocking(move || quota.usage()).await?;
+            let available = self.max.saturating_sub(used);
+            if needed <= available {
+                break;
+            }
+            if !waiting {
+                waiting = true;
+                tracer.set_status(Status::WaitingForSpace);
+                tracer
+                    .sink
+                    .on_waiting_for_space(&self.dir, needed, available);
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+        if waiting {
+            tracer.set_status(Status::Running);
+        }
+        Ok(())
+    }
+}
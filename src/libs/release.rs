@@ -0,0 +1,198 @@
+use super::{DownloadError, Resolved, filename::glob};
+use percent_encoding::{NON_ALPHANUMERIC, utf8_percent_encode};
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Forge {
+    GitHub,
+    GitLab,
+}
+
+#[derive(Clone, Debug)]
+pub struct Release {
+    pub forge: Forge,
+    pub project: String,
+    pub tag: Option<String>,
+    pub pattern: String,
+    pub token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct GitHubRelease {
+    assets: Vec<GitHubAsset>,
+}
+
+#[derive(Deserialize)]
+struct GitHubAsset {
+    name: String,
+    url: String,
+    browser_download_url: String,
+}
+
+#[derive(Deserialize)]
+struct GitLabRelease {
+    assets: GitLabAssets,
+}
+
+#[derive(Deserialize)]
+struct GitLabAssets {
+    links: Vec<GitLabLink>,
+}
+
+#[derive(Deserialize)]
+struct GitLabLink {
+    name: String,
+    url: String,
+    direct_asset_url: Option<String>,
+}
+
+impl Release {
+    pub fn parse(spec: &str) -> Option<Self> {
+        let (spec, pattern) = spec.split_once('#').unwrap_or((spec, "*"));
+        let (forge, project, tag) = match spec.split_once(':') {
+            Some(("gh", rest)) => Self::split(Forge::GitHub, rest),
+            Some(("gl", rest)) => Self::split(Forge::GitLab, rest),
+            _ => Self::page(spec)?,
+        };
+        let token = std::env::var(match forge {
+            Forge::GitHub => "STUPIDL_GITHUB_TOKEN",
+            Forge::GitLab => "STUPIDL_GITLAB_TOKEN",
+        })
+        .ok()
+        .filter(|t| !t.is_empty());
+        (project.split('/').filter(|s| !s.is_empty()).count() >= 2).then(|| Self {
+            forge,
+            project,
+            tag,
+            pattern: pattern.to_owned(),
+            token,
+        })
+    }
+
+    fn split(forge: Forge, rest: &str) -> (Forge, String, Option<String>) {
+        match rest.split_once('@') {
+            Some((project, tag)) if tag != "latest" => {
+                (forge, project.to_owned(), Some(tag.to_owned()))
+            }
+            Some((project, _)) => (forge, project.to_owned(), None),
+            None => (forge, rest.to_owned(), None),
+        }
+    }
+
+    fn page(url: &str) -> Option<(Forge, String, Option<String>)> {
+        let url = Url::parse(url).ok()?;
+        let path = url.path().trim_matches('/');
+        match url.host_str()? {
+            "github.com" => {
+                let segments: Vec<_> = path.split('/').collect();
+                match segments.as_slice() {
+                    [owner, repo, "releases", "latest"] => {
+                        Some((Forge::GitHub, format!("{}/{}", owner, repo), None))
+                    }
+                    [owner, repo, "releases", "tag", tag] => Some((
+                        Forge::GitHub,
+                        format!("{}/{}", owner, repo),
+                        Some(tag.to_string()),
+                    )),
+                    _ => None,
+                }
+            }
+            "gitlab.com" => {
+                let (project, release) = path.split_once("/-/releases")?;
+                let tag = release.trim_matches('/');
+                Some((
+                    Forge::GitLab,
+                    project.to_owned(),
+                    (!tag.is_empty() && tag != "permalink/latest").then(|| tag.to_owned()),
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    /// Looks the asset up over `client`, normally `DownloaderConfig::client`.
+    pub async fn resolve(&self, client: &Client) -> Result<Resolved, DownloadError> {
+        let assets = match self.forge {
+            Forge::GitHub => self.github(client).await?,
+            Forge::GitLab => self.gitlab(client).await?,
+        };
+        let (_, url, headers) = assets
+            .into_iter()
+            .find(|(name, _, _)| glob(&self.pattern, name))
+            .ok_or_else(|| DownloadError::Release(format!("no asset matching {}", self.pattern)))?;
+        Ok(Resolved {
+            urls: vec![url],
+            headers,
+            checksum: None,
+        })
+    }
+
+    async fn github(
+        &self,
+        client: &Client,
+    ) -> Result<Vec<(String, String, HashMap<String, String>)>, DownloadError> {
+        let endpoint = match &self.tag {
+            Some(tag) => format!(
+                "https://api.github.com/repos/{}/releases/tags/{}",
+                self.project, tag
+            ),
+            None => format!(
+                "https://api.github.com/repos/{}/releases/latest",
+                self.project
+            ),
+        };
+        let mut request = client
+            .get(endpoint)
+            .header("Accept", "application/vnd.github+json");
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+        let release: GitHubRelease = request.send().await?.error_for_status()?.json().await?;
+        Ok(release
+            .assets
+            .into_iter()
+            .map(|a| match &self.token {
+                Some(token) => (
+                    a.name,
+                    a.url,
+                    HashMap::from([
+                        ("Accept".to_owned(), "application/octet-stream".to_owned()),
+                        ("Authorization".to_owned(), format!("Bearer {}", token)),
+                    ]),
+                ),
+                None => (a.name, a.browser_download_url, HashMap::new()),
+            })
+            .collect())
+    }
+
+    async fn gitlab(
+        &self,
+        client: &Client,
+    ) -> Result<Vec<(String, String, HashMap<String, String>)>, DownloadError> {
+        let project = utf8_percent_encode(&self.project, NON_ALPHANUMERIC);
+        let tag = match &self.tag {
+            Some(tag) => utf8_percent_encode(tag, NON_ALPHANUMERIC).to_string(),
+            None => "permalink/latest".to_owned(),
+        };
+        let mut request = client.get(format!(
+            "https://gitlab.com/api/v4/projects/{}/releases/{}",
+            project, tag
+        ));
+        let headers = match &self.token {
+            Some(token) => {
+                request = request.header("PRIVATE-TOKEN", token);
+                HashMap::from([("PRIVATE-TOKEN".to_owned(), token.clone())])
+            }
+            None => HashMap::new(),
+        };
+        let release: GitLabRelease = request.send().await?.error_for_status()?.json().await?;
+        Ok(release
+            .assets
+            .links
+            .into_iter()
+            .map(|l| (l.name, l.direct_asset_url.unwrap_or(l.url), headers.clone()))
+            .collect())
+    }
+}
@@ -0,0 +1,88 @@
+use super::DownloadError;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Clone, Debug)]
+pub struct Sandbox {
+    roots: Vec<PathBuf>,
+}
+
+impl Sandbox {
+    pub fn new<P: AsRef<Path>>(roots: impl IntoIterator<Item = P>) -> Result<Self, DownloadError> {
+        let roots = roots
+            .into_iter()
+            .map(|r| r.as_ref().canonicalize())
+            .collect::<Result<_, _>>()?;
+        Ok(Self { roots })
+    }
+
+    /// Roots from `STUPIDL_SANDBOX`; set but unusable is an error rather
+    /// than no sandbox at all.
+    pub fn from_env() -> Result<Option<Self>, DownloadError> {
+        let Some(roots) = std::env::var_os("STUPIDL_SANDBOX") else {
+            return Ok(None);
+        };
+        let invalid = |reason: String| DownloadError::InvalidSandbox(reason);
+        let roots: Vec<_> = std::env::split_paths(&roots)
+            .filter(|r| !r.as_os_str().is_empty())
+            .collect();
+        if roots.is_empty() {
+            return Err(invalid("no directories given".to_owned()));
+        }
+        Self::new(&roots)
+            .map(Some)
+            .map_err(|e| invalid(e.to_string()))
+    }
+
+    pub fn check(&self, path: impl AsRef<Path>) -> Result<PathBuf, DownloadError> {
+        let path = path.as_ref();
+        let outside = || DownloadError::OutsideSandbox(path.display().to_string());
+        let name = match path.components().next_back() {
+            Some(Component::Normal(name)) => name,
+            _ => return Err(outside()),
+        };
+        let parent = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => resolve(p).ok_or_else(outside)?,
+            _ => std::env::current_dir()?.canonicalize()?,
+        };
+        let resolved = parent.join(name);
+        if resolved.is_symlink() {
+            return Err(outside());
+        }
+        self.roots
+            .iter()
+            .any(|root| resolved.starts_with(root))
+            .then_some(resolved)
+            .ok_or_else(outside)
+    }
+}
+
+/// Canonicalizes the deepest existing ancestor of `dir` and appends the
+/// folders still to be created, which must be plain names.
+fn resolve(dir: &Path) -> Option<PathBuf> {
+    let dir = std::path::absolute(dir).ok()?;
+    let base = dir
+        .ancestors()
+        .find_map(|a| Some((a, a.canonicalize().ok()?)))?;
+    let rest = dir.strip_prefix(base.0).ok()?;
+    rest.components()
+        .all(|c| matches!(c, Component::Normal(_)))
+        .then(|| base.1.join(rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allows_new_folders_but_not_climbing_out() {
+        let root = std::env::temp_dir().join(format!("stupidl-sandbox-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("inside")).unwrap();
+        let sandbox = Sandbox::new([root.join("inside")]).unwrap();
+        let resolved = sandbox.check(root.join("inside/new/deeper/a.bin")).unwrap();
+        assert!(resolved.ends_with("new/deeper/a.bin"));
+        assert!(sandbox.check(root.join("inside/new/../../a.bin")).is_err());
+        assert!(sandbox.check(root.join("a.bin")).is_err());
+        assert!(sandbox.check(root.join("inside/..")).is_err());
+        std::fs::remove_dir_all(root).ok();
+    }
+}
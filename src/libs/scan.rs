@@ -0,0 +1,134 @@
+use super::{DownloadError, Vars};
+use std::process::Stdio;
+use tokio::process::Command;
+
+#[cfg(windows)]
+const DEFENDER: &str = r"C:\Program Files\Windows Defender\MpCmdRun.exe";
+/// MpCmdRun's exit code when it found a threat; anything else non-zero means
+/// the scan itself failed.
+#[cfg(windows)]
+const DEFENDER_INFECTED: i32 = 2;
+/// clamscan and most command line scanners exit with 1 on a detection.
+const INFECTED: i32 = 1;
+
+pub enum Scanner {
+    #[cfg(windows)]
+    Defender,
+    /// Arguments are split like a shell would, so quote paths with spaces.
+    Command { line: String, infected: Vec<i32> },
+}
+
+impl Scanner {
+    pub fn from_env() -> Option<Self> {
+        match std::env::var("STUPIDL_SCANNER").ok()?.as_str() {
+            "" => None,
+            #[cfg(windows)]
+            "defender" => Some(Self::Defender),
+            line => Some(Self::Command {
+                line: line.to_owned(),
+                infected: std::env::var("STUPIDL_SCANNER_INFECTED")
+                    .ok()
+                    .map(|codes| {
+                        codes
+                            .split(',')
+                            .filter_map(|c| c.trim().parse().ok())
+                            .collect()
+                    })
+                    .unwrap_or(vec![INFECTED]),
+            }),
+        }
+    }
+
+    pub async fn scan(&self, vars: &Vars) -> Result<(), DownloadError> {
+        let path = vars.get("path").unwrap_or_default();
+        let (program, args, infected): (String, Vec<String>, &[i32]) = match self {
+            #[cfg(windows)]
+            Self::Defender => (
+                DEFENDER.to_owned(),
+                [
+                    "-Scan",
+                    "-ScanType",
+                    "3",
+                    "-DisableRemediation",
+                    "-File",
+                    path,
+                ]
+                .map(str::to_owned)
+                .to_vec(),
+                &[DEFENDER_INFECTED][..],
+            ),
+            Self::Command { line, infected } => {
+                let words = shell_words::split(line)
+                    .map_err(|e| DownloadError::ScanError(e.to_string()))?;
+                let mut parts = words.iter().map(|p| vars.render(p));
+                let program = parts
+                    .next()
+                    .ok_or_else(|| DownloadError::ScanError("empty scanner command".to_owned()))?;
+                (program, parts.collect(), &infected[..])
+            }
+        };
+        let status = Command::new(&program)
+            .args(args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .await
+            .map_err(|e| DownloadError::ScanError(format!("{}: {}", program, e)))?;
+        match status.code() {
+            Some(0) => Ok(()),
+            Some(code) if infected.contains(&code) => {
+                let quarantined = format!("{}.quarantine", path);
+                tokio::fs::rename(path, &quarantined).await?;
+                Err(DownloadError::ScanFailed(quarantined))
+            }
+            _ => Err(DownloadError::ScanError(format!(
+                "{} exited with {}",
+                program, status
+            ))),
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn scan(line: &str, name: &str) -> (Result<(), DownloadError>, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!("stupidl-scan-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, b"data").unwrap();
+        let scanner = Scanner::Command {
+            line: line.to_owned(),
+            infected: vec![INFECTED],
+        };
+        let vars = Vars::new().with("path", path.to_string_lossy());
+        let result = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(scanner.scan(&vars));
+        (result, path)
+    }
+
+    #[test]
+    fn clean_file_passes() {
+        let (result, path) = scan("test -f {path}", "clean file.bin");
+        assert!(result.is_ok());
+        std::fs::remove_file(path).ok();
+    }
+
+    #[test]
+    fn infected_exit_code_quarantines() {
+        let (result, path) = scan("sh -c 'exit 1'", "infected.bin");
+        assert!(matches!(result, Err(DownloadError::ScanFailed(_))));
+        assert!(!path.exists());
+        std::fs::remove_file(format!("{}.quarantine", path.display())).ok();
+    }
+
+    #[test]
+    fn scanner_failure_keeps_the_file() {
+        let (result, path) = scan("sh -c 'exit 2'", "error.bin");
+        assert!(matches!(result, Err(DownloadError::ScanError(_))));
+        assert!(path.exists());
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -0,0 +1,57 @@
+use super::{DownloadError, Vars};
+use rhai::{AST, Dynamic, Engine, Map, Scope};
+use std::path::Path;
+
+pub enum Verdict {
+    Continue,
+    Skip,
+    Replace(String),
+}
+
+pub struct Hooks {
+    engine: Engine,
+    ast: AST,
+}
+
+impl Hooks {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, DownloadError> {
+        let engine = Engine::new();
+        let ast = engine
+            .compile_file(path.as_ref().into())
+            .map_err(|e| DownloadError::Script(e.to_string()))?;
+        Ok(Self { engine, ast })
+    }
+
+    pub fn on_url_added(&self, url: &str) -> Result<Verdict, DownloadError> {
+        self.call("on_url_added", &Vars::new().with("url", url))
+    }
+
+    pub fn on_probe(&self, vars: &Vars) -> Result<Verdict, DownloadError> {
+        self.call("on_probe", vars)
+    }
+
+    pub fn on_complete(&self, vars: &Vars) -> Result<(), DownloadError> {
+        self.call("on_complete", vars).map(|_| ())
+    }
+
+    fn call(&self, name: &str, vars: &Vars) -> Result<Verdict, DownloadError> {
+        if !self.ast.iter_functions().any(|f| f.name == name) {
+            return Ok(Verdict::Continue);
+        }
+        let map: Map = vars
+            .iter()
+            .map(|(k, v)| (k.into(), Dynamic::from(v.to_owned())))
+            .collect();
+        let value = self
+            .engine
+            .call_fn::<Dynamic>(&mut Scope::new(), &self.ast, name, (map,))
+            .map_err(|e| DownloadError::Script(e.to_string()))?;
+        Ok(if value.as_bool() == Ok(false) {
+            Verdict::Skip
+        } else if value.is_string() {
+            Verdict::Replace(value.into_string().unwrap_or_default())
+        } else {
+            Verdict::Continue
+        })
+    }
+}
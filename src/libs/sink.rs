@@ -0,0 +1,15 @@
+use super::{DownloadError, Progress};
+use std::path::Path;
+
+pub trait ProgressSink: Send + Sync {
+    fn on_start(&self, _progress: &Progress) {}
+    fn on_chunk_complete(&self, _chunk: u64) {}
+    fn on_chunk_retry(&self, _chunk: u64) {}
+    fn on_progress(&self, _progress: &Progress) {}
+    fn on_mirror_failed(&self, _mirror: &str, _error: &DownloadError) {}
+    fn on_waiting_for_space(&self, _dir: &Path, _needed: u64, _available: u64) {}
+    fn on_error(&self, _error: &DownloadError) {}
+    fn on_finish(&self, _progress: &Progress) {}
+}
+
+impl ProgressSink for () {}
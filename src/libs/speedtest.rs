@@ -0,0 +1,114 @@
+use super::{DownloadError, consts::*};
+use futures_util::{StreamExt, future::join_all};
+use reqwest::{Client, header::CONTENT_RANGE};
+use serde::{Deserialize, Serialize};
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+pub const TEST_URL: &str = "https://speed.cloudflare.com/__down?bytes=1073741824";
+const LEVELS: [usize; 6] = [1, 2, 4, 8, 16, 32];
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Sample {
+    pub connections: usize,
+    pub speed: f64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpeedTest {
+    pub samples: Vec<Sample>,
+}
+
+impl SpeedTest {
+    pub async fn run(
+        client: &Client,
+        url: &str,
+        duration: Duration,
+    ) -> Result<Self, DownloadError> {
+        let total = length(client, url).await?;
+        let mut samples = Vec::new();
+        for connections in LEVELS {
+            let speed = measure(client, url, total, connections, duration).await?;
+            samples.push(Sample { connections, speed });
+        }
+        Ok(Self { samples })
+    }
+
+    /// A result saved by `save`, for `DownloaderConfig::calibrate`.
+    pub fn load(path: impl AsRef<Path>) -> Option<Self> {
+        serde_json::from_slice(&std::fs::read(path).ok()?).ok()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), DownloadError> {
+        let json = serde_json::to_vec_pretty(self).map_err(std::io::Error::from)?;
+        Ok(std::fs::write(path, json)?)
+    }
+
+    pub fn best(&self) -> f64 {
+        self.samples.iter().map(|s| s.speed).fold(0.0, f64::max)
+    }
+
+    pub fn optimal(&self) -> usize {
+        let best = self.best();
+        self.samples
+            .iter()
+            .find(|s| s.speed >= best * 0.9)
+            .map_or(1, |s| s.connections)
+    }
+}
+
+/// Size of the test file, 0 if the server doesn't say.
+async fn length(client: &Client, url: &str) -> Result<u64, DownloadError> {
+    let response = client
+        .get(url)
+        .header("Range", "bytes=0-0")
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(response
+        .headers()
+        .get(CONTENT_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit_once('/'))
+        .and_then(|(_, total)| total.parse().ok())
+        .or(response.content_length())
+        .unwrap_or_default())
+}
+
+async fn measure(
+    client: &Client,
+    url: &str,
+    total: u64,
+    connections: usize,
+    duration: Duration,
+) -> Result<f64, DownloadError> {
+    let begin = Instant::now();
+    let received = join_all((0..connections as u64).map(|i| async move {
+        // Offsets wrap around the file, so none asks for a range past its end
+        let offset = match total {
+            0 => 0,
+            total => i * 64 * MB % total,
+        };
+        let response = client
+            .get(url)
+            .header("Range", format!("bytes={}-", offset))
+            .send()
+            .await?
+            .error_for_status()?;
+        let mut stream = response.bytes_stream();
+        let mut received = 0;
+        while begin.elapsed() < duration {
+            match tokio::time::timeout(duration, stream.next()).await {
+                Ok(Some(chunk)) => received += chunk?.len() as u64,
+                _ => break,
+            }
+        }
+        Ok::<_, DownloadError>(received)
+    }))
+    .await
+    .into_iter()
+    .collect::<Result<Vec<_>, _>>()?;
+    Ok(received.iter().sum::<u64>() as f64 / begin.elapsed().as_secs_f64())
+}
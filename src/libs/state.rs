@@ -0,0 +1,153 @@
+use super::{DownloadError, attrs};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+pub const SAMPLE: usize = 4096;
+const VERIFIED_SAMPLES: usize = 8;
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct State {
+    pub url: String,
+    pub total_size: u64,
+    pub chunk_size: u64,
+    pub etag: Option<String>,
+    #[serde(default)]
+    pub last_modified: Option<String>,
+    pub done: Vec<u64>,
+    #[serde(default)]
+    pub samples: BTreeMap<u64, u64>,
+}
+
+impl State {
+    pub fn load(path: &Path) -> Option<Self> {
+        serde_json::from_slice(&std::fs::read(path).ok()?).ok()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), DownloadError> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        std::fs::write(
+            &tmp,
+            serde_json::to_vec(self).map_err(std::io::Error::from)?,
+        )?;
+        std::fs::rename(tmp, path)?;
+        attrs::hide(path);
+        Ok(())
+    }
+
+    pub fn resumes(&self, fresh: &Self) -> bool {
+        self.url == fresh.url
+            && self.total_size == fresh.total_size
+            && self.chunk_size == fresh.chunk_size
+            && self.etag == fresh.etag
+            && self.last_modified == fresh.last_modified
+    }
+
+    pub fn validator(&self) -> Option<&str> {
+        self.etag
+            .as_deref()
+            .filter(|e| !e.starts_with("W/"))
+            .or(self.last_modified.as_deref())
+    }
+
+    pub fn verify(&self, output: &Path) -> bool {
+        let Ok(mut file) = File::open(output) else {
+            return false;
+        };
+        if file.metadata().map(|m| m.len()).ok() != Some(self.total_size) {
+            return false;
+        }
+        let step = (self.samples.len() / VERIFIED_SAMPLES).max(1);
+        self.samples.iter().step_by(step).all(|(&chunk, &hash)| {
+            let start = chunk * self.chunk_size;
+            let mut buf = vec![0; (self.total_size - start).min(SAMPLE as u64) as usize];
+            file.seek(SeekFrom::Start(start)).is_ok()
+                && file.read_exact(&mut buf).is_ok()
+                && fingerprint(&buf) == hash
+        })
+    }
+
+    pub fn is_done(&self, chunk: u64) -> bool {
+        self.done
+            .get((chunk / 64) as usize)
+            .is_some_and(|word| word >> (chunk % 64) & 1 == 1)
+    }
+
+    pub fn mark(&mut self, chunk: u64) {
+        let word = (chunk / 64) as usize;
+        if self.done.len() <= word {
+            self.done.resize(word + 1, 0);
+        }
+        self.done[word] |= 1 << (chunk % 64);
+    }
+}
+
+pub(super) struct Journal {
+    path: PathBuf,
+    inner: Mutex<(State, Instant)>,
+}
+
+impl Journal {
+    pub fn new(path: PathBuf, state: State) -> Self {
+        Self {
+            path,
+            inner: Mutex::new((state, Instant::now())),
+        }
+    }
+
+    pub fn validator(&self) -> Option<String> {
+        self.inner.lock().unwrap().0.validator().map(str::to_owned)
+    }
+
+    pub fn reset(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.0.done.clear();
+        inner.0.samples.clear();
+    }
+
+    pub fn is_done(&self, chunk: u64) -> bool {
+        self.inner.lock().unwrap().0.is_done(chunk)
+    }
+
+    /// Number of chunks done from the start of the file without a gap.
+    pub fn prefix(&self) -> u64 {
+        let inner = self.inner.lock().unwrap();
+        (0..).take_while(|&chunk| inner.0.is_done(chunk)).count() as u64
+    }
+
+    pub fn complete(&self, chunk: u64, sample: u64) -> Result<(), DownloadError> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.0.mark(chunk);
+        inner.0.samples.insert(chunk, sample);
+        if inner.1.elapsed() >= SAVE_INTERVAL {
+            inner.1 = Instant::now();
+            inner.0.save(&self.path)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&self) -> Result<(), DownloadError> {
+        self.inner.lock().unwrap().0.save(&self.path)
+    }
+
+    pub fn remove(&self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+pub fn fingerprint(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
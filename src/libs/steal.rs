@@ -0,0 +1,112 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+const SETTLE: Duration = Duration::from_secs(1);
+
+pub(super) struct Part {
+    pub chunk: u64,
+    pub owner: bool,
+    start: u64,
+    span: Mutex<(u64, u64)>,
+    begin: Instant,
+}
+
+impl Part {
+    fn new(chunk: u64, owner: bool, start: u64, end: u64) -> Arc<Self> {
+        Arc::new(Self {
+            chunk,
+            owner,
+            start,
+            span: Mutex::new((start, end)),
+            begin: Instant::now(),
+        })
+    }
+
+    pub fn span(&self) -> (u64, u64) {
+        *self.span.lock().unwrap()
+    }
+
+    pub fn advance(&self, len: u64) -> u64 {
+        let mut span = self.span.lock().unwrap();
+        let keep = len.min((span.1 + 1).saturating_sub(span.0));
+        span.0 += keep;
+        keep
+    }
+
+    pub fn done(&self) -> bool {
+        let (next, end) = self.span();
+        next > end
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    parts: Vec<Arc<Part>>,
+    outstanding: HashMap<u64, usize>,
+    samples: HashMap<u64, u64>,
+}
+
+#[derive(Default)]
+pub(super) struct Board {
+    inner: Mutex<Inner>,
+}
+
+impl Board {
+    pub fn register(&self, chunk: u64, start: u64, end: u64) -> Arc<Part> {
+        let part = Part::new(chunk, true, start, end);
+        let mut inner = self.inner.lock().unwrap();
+        inner.parts.push(part.clone());
+        *inner.outstanding.entry(chunk).or_default() += 1;
+        part
+    }
+
+    pub fn finish(&self, part: &Arc<Part>, sample: Option<u64>) -> Option<u64> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.parts.retain(|p| !Arc::ptr_eq(p, part));
+        if let Some(sample) = sample {
+            inner.samples.insert(part.chunk, sample);
+        }
+        let outstanding = inner.outstanding.entry(part.chunk).or_default();
+        *outstanding = outstanding.saturating_sub(1);
+        match *outstanding {
+            0 => {
+                inner.outstanding.remove(&part.chunk);
+                Some(inner.samples.remove(&part.chunk).unwrap_or_default())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn steal(&self, min: u64) -> Option<Arc<Part>> {
+        let mut inner = self.inner.lock().unwrap();
+        let victim = inner
+            .parts
+            .iter()
+            .filter(|p| p.begin.elapsed() >= SETTLE)
+            .filter_map(|p| {
+                let (next, end) = p.span();
+                let remaining = (end + 1).saturating_sub(next);
+                let rate = (next - p.start) as f64 / p.begin.elapsed().as_secs_f64();
+                (remaining >= min).then(|| (p.clone(), remaining as f64 / rate.max(1.0)))
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(p, _)| p)?;
+        let stolen = {
+            let mut span = victim.span.lock().unwrap();
+            let remaining = (span.1 + 1).saturating_sub(span.0);
+            if remaining < min {
+                return None;
+            }
+            let mid = span.0 + remaining / 2;
+            let stolen = Part::new(victim.chunk, false, mid, span.1);
+            span.1 = mid - 1;
+            stolen
+        };
+        inner.parts.push(stolen.clone());
+        *inner.outstanding.entry(victim.chunk).or_default() += 1;
+        Some(stolen)
+    }
+}
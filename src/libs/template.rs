@@ -0,0 +1,116 @@
+use super::Category;
+use reqwest::Url;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Default)]
+pub struct Vars(HashMap<String, String>);
+
+impl Vars {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn for_download(url: &str, path: &str) -> Self {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_owned))
+            .unwrap_or_default();
+        Self::new()
+            .with("url", url)
+            .with("path", path)
+            .with("host", host)
+            .with("category", Category::from_name(path).as_str())
+    }
+
+    pub fn with(mut self, key: &str, value: impl Into<String>) -> Self {
+        self.set(key, value);
+        self
+    }
+
+    pub fn set(&mut self, key: &str, value: impl Into<String>) {
+        self.0.insert(key.to_owned(), value.into());
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// Replaces `{key}` with its value. `{{` and `}}` stand for literal
+    /// braces; unknown keys and unclosed braces are kept as written.
+    pub fn render(&self, template: &str) -> String {
+        let mut out = String::with_capacity(template.len());
+        let mut rest = template;
+        while let Some(start) = rest.find(['{', '}']) {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+            if let Some(stripped) = rest.strip_prefix("{{") {
+                out.push('{');
+                rest = stripped;
+                continue;
+            }
+            if let Some(stripped) = rest.strip_prefix('}') {
+                out.push('}');
+                rest = stripped.strip_prefix('}').unwrap_or(stripped);
+                continue;
+            }
+            match rest
+                .find('}')
+                .and_then(|end| Some((self.get(&rest[1..end])?, end)))
+            {
+                Some((value, end)) => {
+                    out.push_str(value);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    out.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Vars;
+
+    fn vars() -> Vars {
+        Vars::new().with("name", "file.iso").with("empty", "")
+    }
+
+    #[test]
+    fn substitutes_known_keys() {
+        assert_eq!(vars().render("{name}.part"), "file.iso.part");
+        assert_eq!(vars().render("{name}/{name}"), "file.iso/file.iso");
+    }
+
+    #[test]
+    fn keeps_unknown_keys() {
+        assert_eq!(vars().render("{nope} {name}"), "{nope} file.iso");
+    }
+
+    #[test]
+    fn empty_values_render_empty() {
+        assert_eq!(vars().render("[{empty}]"), "[]");
+    }
+
+    #[test]
+    fn escapes_braces() {
+        assert_eq!(vars().render("{{name}}"), "{name}");
+        assert_eq!(vars().render("{{{name}}}"), "{file.iso}");
+        assert_eq!(vars().render("a}}b{{c"), "a}b{c");
+    }
+
+    #[test]
+    fn keeps_unclosed_and_stray_braces() {
+        assert_eq!(vars().render("{name"), "{name");
+        assert_eq!(vars().render("a}b"), "a}b");
+        assert_eq!(vars().render("{}"), "{}");
+    }
+}
@@ -0,0 +1,147 @@
+use reqwest::{Client, Request, RequestBuilder, Response, Url, header::HeaderMap};
+use std::{
+    fmt::Write as _,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::Mutex,
+    time::Instant,
+};
+
+const SECRET_HEADERS: [&str; 6] = [
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "private-token",
+    "x-api-key",
+];
+const SECRET_PARAMS: [&str; 6] = ["token", "sig", "key", "auth", "credential", "password"];
+
+#[derive(Default)]
+struct Log {
+    pending: Vec<String>,
+    file: Option<File>,
+}
+
+pub struct Trace {
+    begin: Instant,
+    log: Option<Mutex<Log>>,
+}
+
+impl Trace {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            begin: Instant::now(),
+            log: enabled.then(Mutex::default),
+        }
+    }
+
+    pub fn attach(&self, path: &Path) {
+        let Some(log) = &self.log else {
+            return;
+        };
+        let mut log = log.lock().unwrap();
+        if log.file.is_none()
+            && let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path)
+        {
+            for entry in log.pending.drain(..) {
+                file.write_all(entry.as_bytes()).ok();
+            }
+            log.file = Some(file);
+        }
+    }
+
+    pub async fn send(
+        &self,
+        client: &Client,
+        label: &str,
+        builder: RequestBuilder,
+    ) -> reqwest::Result<Response> {
+        let request = builder.build()?;
+        if self.log.is_none() {
+            return client.execute(request).await;
+        }
+        self.request(label, &request);
+        let sent = Instant::now();
+        let result = client.execute(request).await;
+        let mut entry = self.stamp(label, '<');
+        match &result {
+            Ok(response) => {
+                writeln!(entry, "{} ({:?})", response.status(), sent.elapsed()).ok();
+                headers(&mut entry, response.headers());
+            }
+            Err(e) => {
+                writeln!(entry, "error: {} ({:?})", e, sent.elapsed()).ok();
+            }
+        }
+        self.write(entry);
+        result
+    }
+
+    fn request(&self, label: &str, request: &Request) {
+        let mut entry = self.stamp(label, '>');
+        writeln!(entry, "{} {}", request.method(), redact(request.url())).ok();
+        headers(&mut entry, request.headers());
+        self.write(entry);
+    }
+
+    fn stamp(&self, label: &str, direction: char) -> String {
+        format!(
+            "[+{:.3}s] {} {} ",
+            self.begin.elapsed().as_secs_f64(),
+            label,
+            direction
+        )
+    }
+
+    fn write(&self, entry: String) {
+        let Some(log) = &self.log else {
+            return;
+        };
+        let mut log = log.lock().unwrap();
+        match &mut log.file {
+            Some(file) => {
+                file.write_all(entry.as_bytes()).ok();
+            }
+            None => log.pending.push(entry),
+        }
+    }
+}
+
+fn headers(entry: &mut String, headers: &HeaderMap) {
+    for (name, value) in headers {
+        let value = match SECRET_HEADERS.contains(&name.as_str()) {
+            true => "<redacted>",
+            false => value.to_str().unwrap_or("<binary>"),
+        };
+        writeln!(entry, "    {}: {}", name, value).ok();
+    }
+}
+
+fn redact(url: &Url) -> String {
+    let mut url = url.clone();
+    if url.query().is_some() {
+        let pairs: Vec<_> = url
+            .query_pairs()
+            .map(|(k, v)| {
+                let secret = SECRET_PARAMS
+                    .iter()
+                    .any(|s| k.to_ascii_lowercase().contains(s));
+                (
+                    k.into_owned(),
+                    if secret {
+                        "<redacted>".into()
+                    } else {
+                        v.into_owned()
+                    },
+                )
+            })
+            .collect();
+        url.query_pairs_mut().clear().extend_pairs(pairs);
+    }
+    if url.password().is_some() {
+        url.set_password(Some("<redacted>")).ok();
+    }
+    url.to_string()
+}
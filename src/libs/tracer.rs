@@ -0,0 +1,142 @@
+use super::ProgressSink;
+use std::{
+    collections::VecDeque,
+    sync::{
+        Arc, Mutex, MutexGuard, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::watch::Sender;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+const SPEED_WINDOW: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Status {
+    #[default]
+    Pending,
+    Running,
+    Paused,
+    WaitingForSpace,
+    Finished,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProgressKind {
+    Determinate(f64),
+    Indeterminate,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Progress {
+    pub downloaded: u64,
+    pub total: u64,
+    pub speed: f64,
+    pub status: Status,
+}
+
+impl Progress {
+    pub fn fraction(&self) -> Option<f64> {
+        (self.total > 0).then(|| (self.downloaded as f64 / self.total as f64).min(1.0))
+    }
+
+    pub fn kind(&self) -> ProgressKind {
+        self.fraction()
+            .map_or(ProgressKind::Indeterminate, ProgressKind::Determinate)
+    }
+
+    pub fn percent(&self) -> f64 {
+        self.fraction().map_or(0.0, |f| f * 100.0)
+    }
+}
+
+#[derive(Clone)]
+pub struct Tracer {
+    pub total_size: u64,
+    counter: Arc<AtomicU64>,
+    meter: Arc<Mutex<VecDeque<(Instant, u64)>>>,
+    pub sender: Sender<Progress>,
+    pub sink: Arc<dyn ProgressSink>,
+}
+
+impl Tracer {
+    pub fn new(total_size: u64) -> Self {
+        Self {
+            total_size,
+            counter: Arc::new(AtomicU64::new(0)),
+            meter: Arc::new(Mutex::new(VecDeque::new())),
+            sender: Sender::new(Progress {
+                total: total_size,
+                ..Default::default()
+            }),
+            sink: Arc::new(()),
+        }
+    }
+
+    pub fn reset(&self, downloaded: u64) {
+        self.counter.store(downloaded, Ordering::Relaxed);
+        self.meter().clear();
+    }
+
+    pub fn add(&self, size: u64) {
+        let downloaded = self.counter.fetch_add(size, Ordering::Relaxed) + size;
+        let now = Instant::now();
+        let mut meter = self.meter();
+        if meter
+            .back()
+            .is_none_or(|(time, _)| now - *time >= SAMPLE_INTERVAL)
+        {
+            meter.push_back((now, downloaded));
+            while meter.len() > 2 && meter.front().is_some_and(|(t, _)| now - *t > SPEED_WINDOW) {
+                meter.pop_front();
+            }
+        }
+        drop(meter);
+        let speed = self.speed();
+        self.sender.send_modify(|p| {
+            p.downloaded = downloaded;
+            p.speed = speed;
+        });
+        self.sink.on_progress(&self.progress());
+    }
+
+    fn meter(&self) -> MutexGuard<'_, VecDeque<(Instant, u64)>> {
+        self.meter.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn progress(&self) -> Progress {
+        *self.sender.borrow()
+    }
+
+    pub fn set_status(&self, status: Status) {
+        self.sender.send_modify(|p| {
+            p.status = status;
+            p.downloaded = self.downloaded();
+        });
+    }
+
+    pub fn downloaded(&self) -> u64 {
+        self.counter.load(Ordering::Relaxed)
+    }
+
+    pub fn speed(&self) -> f64 {
+        let meter = self.meter();
+        match meter.front() {
+            Some(&(since, base)) if since.elapsed() > Duration::ZERO => {
+                self.downloaded().saturating_sub(base) as f64 / since.elapsed().as_secs_f64()
+            }
+            _ => 0.0,
+        }
+    }
+
+    pub fn eta(&self) -> Option<Duration> {
+        let speed = self.speed();
+        (speed > 0.0 && self.total_size > 0).then(|| {
+            let remaining = self.total_size.saturating_sub(self.downloaded());
+            Duration::from_secs_f64(remaining as f64 / speed)
+        })
+    }
+}
@@ -0,0 +1,69 @@
+use super::{Tracer, gate::Gate, limiter::Limiter};
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, Mutex, PoisonError},
+    time::Duration,
+};
+use tokio::sync::watch::Receiver;
+
+const WINDOW: Duration = Duration::from_secs(5);
+// Longest wait for dropped connections to finish their chunks
+const SETTLE: Duration = Duration::from_secs(30);
+// Twice the connections have to buy at least this much more throughput
+const GAIN: f64 = 1.1;
+
+static LEARNED: LazyLock<Mutex<HashMap<String, usize>>> = LazyLock::new(Default::default);
+
+/// Connection count that served `host` just as well earlier in this run.
+pub fn learned(host: &str) -> Option<usize> {
+    let learned = LEARNED.lock().unwrap_or_else(PoisonError::into_inner);
+    learned.get(host).copied()
+}
+
+async fn measure(tracer: &Tracer, pause: &mut Receiver<bool>) -> f64 {
+    pause.wait_for(|p| !p).await.ok();
+    let before = tracer.downloaded();
+    tokio::time::sleep(WINDOW).await;
+    tracer.downloaded().saturating_sub(before) as f64 / WINDOW.as_secs_f64()
+}
+
+/// Waits until connections above the gate's limit have given their
+/// permits back, so a sample really runs at the lowered count.
+async fn settle(gate: &Gate) -> bool {
+    let drained = async {
+        while gate.active() > gate.limit() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    };
+    tokio::time::timeout(SETTLE, drained).await.is_ok()
+}
+
+/// Halves the connection count for as long as that costs no throughput,
+/// so small servers aren't hammered while fast CDNs keep every connection.
+/// A rate limit flattens every sample, so nothing is tuned under one.
+pub async fn tune(
+    host: String,
+    gate: Arc<Gate>,
+    limiter: Arc<Limiter>,
+    tracer: Tracer,
+    mut pause: Receiver<bool>,
+) {
+    let limited = || limiter.rate() > 0;
+    let mut speed = measure(&tracer, &mut pause).await;
+    while gate.limit() > 1 && speed > 0.0 && !limited() {
+        let limit = gate.limit();
+        gate.set_limit(limit / 2);
+        if !settle(&gate).await {
+            gate.set_limit(limit);
+            return;
+        }
+        let reduced = measure(&tracer, &mut pause).await;
+        if reduced * GAIN < speed || limited() {
+            gate.set_limit(limit);
+            return;
+        }
+        speed = reduced;
+        let mut learned = LEARNED.lock().unwrap_or_else(PoisonError::into_inner);
+        learned.insert(host.clone(), limit / 2);
+    }
+}
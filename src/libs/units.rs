@@ -0,0 +1,195 @@
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Units {
+    /// KiB, MiB, ... in steps of 1024
+    #[default]
+    Binary,
+    /// kB, MB, ... in steps of 1000
+    Decimal,
+}
+
+/// How sizes, speeds and durations are shown to people, shared by the GUI,
+/// the CLI and notifications.
+#[derive(Clone, Copy, Debug)]
+pub struct Format {
+    pub units: Units,
+    pub decimal: char,
+    pub grouping: Option<char>,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Self {
+            units: Units::Binary,
+            decimal: '.',
+            grouping: Some(','),
+        }
+    }
+}
+
+// Languages whose numbers read 1.234,5 or 1 234,5
+const COMMA_LOCALES: [&str; 16] = [
+    "de", "es", "it", "nl", "pt", "tr", "id", "da", "ro", "fr", "ru", "uk", "pl", "cs", "sv", "fi",
+];
+const SPACE_GROUPED: [&str; 8] = ["fr", "ru", "uk", "pl", "cs", "sv", "fi", "nb"];
+
+impl Format {
+    /// Separators from `LC_ALL`/`LC_NUMERIC`/`LANG`, units from
+    /// `STUPIDL_UNITS` (`binary` or `decimal`).
+    pub fn from_env() -> Self {
+        let locale = ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .into_iter()
+            .find_map(|key| std::env::var(key).ok().filter(|v| !v.is_empty()))
+            .unwrap_or_default();
+        let language = locale
+            .split(['_', '-', '.'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let mut format = Self::default();
+        if COMMA_LOCALES.contains(&language.as_str()) {
+            format.decimal = ',';
+            format.grouping = Some('.');
+        }
+        if SPACE_GROUPED.contains(&language.as_str()) {
+            format.grouping = Some('\u{a0}');
+        }
+        if let Ok(units) = std::env::var("STUPIDL_UNITS") {
+            match units.trim().to_ascii_lowercase().as_str() {
+                "decimal" | "si" => format.units = Units::Decimal,
+                "binary" | "iec" => format.units = Units::Binary,
+                _ => {}
+            }
+        }
+        format
+    }
+
+    pub fn units(mut self, units: Units) -> Self {
+        self.units = units;
+        self
+    }
+
+    pub fn number(&self, value: f64, precision: usize) -> String {
+        let text = format!("{:.*}", precision, value.abs());
+        let (whole, fraction) = text.split_once('.').unwrap_or((&text, ""));
+        let mut out = String::new();
+        if value < 0.0 {
+            out.push('-');
+        }
+        for (i, digit) in whole.chars().enumerate() {
+            if i > 0
+                && (whole.len() - i) % 3 == 0
+                && let Some(separator) = self.grouping
+            {
+                out.push(separator);
+            }
+            out.push(digit);
+        }
+        if !fraction.is_empty() {
+            out.push(self.decimal);
+            out.push_str(fraction);
+        }
+        out
+    }
+
+    pub fn bytes(&self, bytes: u64) -> String {
+        let (base, names) = match self.units {
+            Units::Binary => (1024.0, ["B", "KiB", "MiB", "GiB", "TiB", "PiB"]),
+            Units::Decimal => (1000.0, ["B", "kB", "MB", "GB", "TB", "PB"]),
+        };
+        let mut value = bytes as f64;
+        let mut unit = 0;
+        while value >= base && unit < names.len() - 1 {
+            value /= base;
+            unit += 1;
+        }
+        let precision = match unit {
+            0 => 0,
+            _ if value < 10.0 => 2,
+            _ if value < 100.0 => 1,
+            _ => 0,
+        };
+        format!("{} {}", self.number(value, precision), names[unit])
+    }
+
+    pub fn speed(&self, bytes_per_sec: f64) -> String {
+        format!("{}/s", self.bytes(bytes_per_sec.max(0.0) as u64))
+    }
+
+    pub fn duration(&self, duration: Duration) -> String {
+        let secs = duration.as_secs();
+        match (secs / 3600, secs / 60 % 60, secs % 60) {
+            (0, 0, s) => format!("{}s", s),
+            (0, m, s) => format!("{}m {:02}s", m, s),
+            (h, m, _) => format!("{}h {:02}m", self.number(h as f64, 0), m),
+        }
+    }
+
+    /// Time left, or `--` while the size or speed isn't known yet.
+    pub fn eta(&self, eta: Option<Duration>) -> String {
+        eta.map_or_else(|| "--".to_owned(), |d| self.duration(d))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bytes_roll_over_at_the_unit_base() {
+        let format = Format::default();
+        assert_eq!(format.bytes(1023), "1,023 B");
+        assert_eq!(format.bytes(1024), "1.00 KiB");
+        assert_eq!(format.bytes(1024 * 1024 - 1), "1,024 KiB");
+        assert_eq!(format.bytes(1024 * 1024), "1.00 MiB");
+        assert_eq!(format.bytes(15 * 1024 * 1024 / 10), "1.50 MiB");
+    }
+
+    #[test]
+    fn decimal_units_step_by_thousands() {
+        let format = Format::default().units(Units::Decimal);
+        assert_eq!(format.bytes(999), "999 B");
+        assert_eq!(format.bytes(1000), "1.00 kB");
+        assert_eq!(format.bytes(25_000_000), "25.0 MB");
+        assert_eq!(format.speed(1500.0), "1.50 kB/s");
+    }
+
+    #[test]
+    fn zero_and_negative_values() {
+        let format = Format::default();
+        assert_eq!(format.bytes(0), "0 B");
+        assert_eq!(format.speed(0.0), "0 B/s");
+        assert_eq!(format.speed(-5.0), "0 B/s");
+        assert_eq!(format.duration(Duration::ZERO), "0s");
+    }
+
+    #[test]
+    fn separators_follow_the_format() {
+        let format = Format {
+            decimal: ',',
+            grouping: Some('.'),
+            ..Format::default()
+        };
+        assert_eq!(format.number(1234567.891, 2), "1.234.567,89");
+        assert_eq!(format.number(-1234.0, 0), "-1.234");
+        let plain = Format {
+            grouping: None,
+            ..Format::default()
+        };
+        assert_eq!(plain.number(1234567.0, 0), "1234567");
+    }
+
+    #[test]
+    fn durations_and_unknown_eta() {
+        let format = Format::default();
+        assert_eq!(format.duration(Duration::from_secs(59)), "59s");
+        assert_eq!(format.duration(Duration::from_secs(65)), "1m 05s");
+        assert_eq!(
+            format.duration(Duration::from_secs(3600 * 1234 + 60)),
+            "1,234h 01m"
+        );
+        assert_eq!(format.eta(Some(Duration::from_secs(90))), "1m 30s");
+        assert_eq!(format.eta(None), "--");
+    }
+}
@@ -0,0 +1,142 @@
+use memmap2::MmapRaw;
+use std::{fs::File, io, path::Path, sync::Arc};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+
+pub const WRITE_BUFFER: usize = 256 * 1024;
+
+#[derive(Clone)]
+pub enum Output {
+    File(Arc<File>),
+    Mapped(Arc<MmapRaw>),
+}
+
+impl Output {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::File(Arc::new(
+            std::fs::OpenOptions::new().write(true).open(path)?,
+        )))
+    }
+
+    /// Maps an already preallocated file so writes become plain copies.
+    pub fn map(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)?;
+        Ok(Self::Mapped(Arc::new(MmapRaw::map_raw(&file)?)))
+    }
+
+    pub async fn write_at(&self, offset: u64, data: Vec<u8>) -> io::Result<()> {
+        match self {
+            Self::File(file) => {
+                let file = file.clone();
+                tokio::task::spawn_blocking(move || write_all_at(&file, &data, offset))
+                    .await
+                    .map_err(io::Error::other)?
+            }
+            Self::Mapped(map) => {
+                let start = usize::try_from(offset).map_err(io::Error::other)?;
+                if start
+                    .checked_add(data.len())
+                    .is_none_or(|end| end > map.len())
+                {
+                    return Err(io::ErrorKind::InvalidInput.into());
+                }
+                unsafe {
+                    std::ptr::copy_nonoverlapping(
+                        data.as_ptr(),
+                        map.as_mut_ptr().add(start),
+                        data.len(),
+                    )
+                };
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn sync(&self) -> io::Result<()> {
+        match self {
+            Self::File(file) => {
+                let file = file.clone();
+                tokio::task::spawn_blocking(move || file.sync_data())
+                    .await
+                    .map_err(io::Error::other)?
+            }
+            Self::Mapped(map) => {
+                let map = map.clone();
+                tokio::task::spawn_blocking(move || map.flush())
+                    .await
+                    .map_err(io::Error::other)?
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn write_all_at(file: &File, data: &[u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, data, offset)
+}
+
+#[cfg(windows)]
+fn write_all_at(file: &File, mut data: &[u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !data.is_empty() {
+        match file.seek_write(data, offset)? {
+            0 => return Err(io::ErrorKind::WriteZero.into()),
+            n => {
+                data = &data[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+enum Request {
+    Write(u64, Vec<u8>),
+    Barrier(oneshot::Sender<()>),
+}
+
+pub struct Writer {
+    sender: mpsc::Sender<Request>,
+}
+
+impl Writer {
+    pub fn spawn(output: Output) -> (Self, JoinHandle<io::Result<()>>) {
+        let (sender, receiver) = mpsc::channel(64);
+        (Self { sender }, tokio::spawn(run(output, receiver)))
+    }
+
+    pub async fn write(&self, offset: u64, data: Vec<u8>) -> io::Result<()> {
+        self.sender
+            .send(Request::Write(offset, data))
+            .await
+            .map_err(|_| stopped())
+    }
+
+    pub async fn barrier(&self) -> io::Result<()> {
+        let (ack, done) = oneshot::channel();
+        self.sender
+            .send(Request::Barrier(ack))
+            .await
+            .map_err(|_| stopped())?;
+        done.await.map_err(|_| stopped())
+    }
+}
+
+fn stopped() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "writer stopped")
+}
+
+async fn run(output: Output, mut receiver: mpsc::Receiver<Request>) -> io::Result<()> {
+    while let Some(request) = receiver.recv().await {
+        match request {
+            Request::Write(offset, data) => output.write_at(offset, data).await?,
+            Request::Barrier(ack) => ack.send(()).unwrap_or_default(),
+        }
+    }
+    output.sync().await
+}
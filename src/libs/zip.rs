@@ -0,0 +1,370 @@
+use super::{DownloadError, Downloader, filename::sanitize};
+use flate2::read::DeflateDecoder;
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+use tokio::sync::mpsc;
+
+const EOCD: u32 = 0x06054b50;
+const EOCD64_LOCATOR: u32 = 0x07064b50;
+const CENTRAL: u32 = 0x02014b50;
+const LOCAL: u32 = 0x04034b50;
+// EOCD record plus the longest possible comment
+const TAIL: u64 = 22 + 0xffff;
+// Compressed bytes fetched per read while extracting a member
+const PIECE: u64 = 4 << 20;
+
+#[derive(Clone, Debug)]
+pub struct Member {
+    pub name: String,
+    pub size: u64,
+    pub compressed_size: u64,
+    pub method: u16,
+    offset: u64,
+}
+
+impl Member {
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with('/')
+    }
+}
+
+fn invalid(reason: &str) -> DownloadError {
+    DownloadError::Archive(reason.to_owned())
+}
+
+fn u16_at(data: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn u64_at(data: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(data.get(at..at + 8)?.try_into().ok()?))
+}
+
+/// Random access to a ZIP, remote through range requests or a local file.
+trait Source {
+    fn len(&self) -> u64;
+
+    async fn read_at(&self, offset: u64, len: u64) -> Result<Vec<u8>, DownloadError>;
+}
+
+impl Source for Downloader {
+    fn len(&self) -> u64 {
+        self.state.total_size
+    }
+
+    async fn read_at(&self, offset: u64, len: u64) -> Result<Vec<u8>, DownloadError> {
+        self.read_range(offset, len).await
+    }
+}
+
+struct Local {
+    path: PathBuf,
+    len: u64,
+}
+
+impl Source for Local {
+    fn len(&self) -> u64 {
+        self.len
+    }
+
+    async fn read_at(&self, offset: u64, len: u64) -> Result<Vec<u8>, DownloadError> {
+        let path = self.path.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut file = File::open(path)?;
+            file.seek(SeekFrom::Start(offset))?;
+            let mut data = Vec::new();
+            file.take(len).read_to_end(&mut data)?;
+            Ok(data)
+        })
+        .await?
+    }
+}
+
+impl Downloader {
+    /// Lists a remote ZIP by reading only its central directory.
+    pub async fn zip_members(&self) -> Result<Vec<Member>, DownloadError> {
+        members(self).await
+    }
+
+    /// Fetches one member's compressed range and unpacks it under `dir`.
+    pub async fn extract_member(&self, member: &Member, dir: &Path) -> Result<(), DownloadError> {
+        if let Some(sandbox) = &self.config.sandbox {
+            sandbox.check(member_path(member, dir))?;
+        }
+        extract(self, member, dir).await
+    }
+}
+
+/// Unpacks every member of the local ZIP at `path` under `dir`.
+pub async fn extract_zip(path: &Path, dir: &Path) -> Result<(), DownloadError> {
+    let source = Local {
+        path: path.to_path_buf(),
+        len: tokio::fs::metadata(path).await?.len(),
+    };
+    for member in members(&source).await? {
+        extract(&source, &member, dir).await?;
+    }
+    Ok(())
+}
+
+async fn members(source: &impl Source) -> Result<Vec<Member>, DownloadError> {
+    let total = source.len();
+    let tail_start = total.saturating_sub(TAIL);
+    let tail = source.read_at(tail_start, total - tail_start).await?;
+    let eocd = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&i| u32_at(&tail, i) == Some(EOCD))
+        .ok_or_else(|| invalid("no end of central directory"))?;
+    let mut size = u32_at(&tail, eocd + 12).unwrap_or_default() as u64;
+    let mut offset = u32_at(&tail, eocd + 16).unwrap_or_default() as u64;
+    if (size == 0xffffffff || offset == 0xffffffff)
+        && let Some(locator) = eocd.checked_sub(20)
+        && u32_at(&tail, locator) == Some(EOCD64_LOCATOR)
+    {
+        let at = u64_at(&tail, locator + 8).unwrap_or_default();
+        let record = source.read_at(at, 56).await?;
+        size = u64_at(&record, 40).ok_or_else(|| invalid("short zip64 record"))?;
+        offset = u64_at(&record, 48).ok_or_else(|| invalid("short zip64 record"))?;
+    }
+    if offset.checked_add(size).is_none_or(|end| end > total) {
+        return Err(invalid("central directory out of bounds"));
+    }
+    let directory = source.read_at(offset, size).await?;
+    let mut members = Vec::new();
+    let mut at = 0;
+    while u32_at(&directory, at) == Some(CENTRAL) {
+        let field = |o| u32_at(&directory, at + o).map(u64::from);
+        let method = u16_at(&directory, at + 10).unwrap_or_default();
+        let mut compressed_size = field(20).ok_or_else(|| invalid("truncated entry"))?;
+        let mut size = field(24).ok_or_else(|| invalid("truncated entry"))?;
+        let name_len = u16_at(&directory, at + 28).unwrap_or_default() as usize;
+        let extra_len = u16_at(&directory, at + 30).unwrap_or_default() as usize;
+        let comment_len = u16_at(&directory, at + 32).unwrap_or_default() as usize;
+        let mut offset = field(42).ok_or_else(|| invalid("truncated entry"))?;
+        let name = directory
+            .get(at + 46..at + 46 + name_len)
+            .ok_or_else(|| invalid("truncated entry"))?;
+        let extra = directory
+            .get(at + 46 + name_len..at + 46 + name_len + extra_len)
+            .unwrap_or_default();
+        let mut e = 0;
+        while let (Some(id), Some(len)) = (u16_at(extra, e), u16_at(extra, e + 2)) {
+            if id == 1 {
+                let mut v = e + 4;
+                for value in [&mut size, &mut compressed_size, &mut offset] {
+                    if *value == 0xffffffff
+                        && let Some(wide) = u64_at(extra, v)
+                    {
+                        *value = wide;
+                        v += 8;
+                    }
+                }
+            }
+            e += 4 + len as usize;
+        }
+        members.push(Member {
+            name: String::from_utf8_lossy(name).into_owned(),
+            size,
+            compressed_size,
+            method,
+            offset,
+        });
+        at += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(members)
+}
+
+/// Where `member` lands under `dir`; names can never climb out of it.
+fn member_path(member: &Member, dir: &Path) -> PathBuf {
+    member
+        .name
+        .split(['/', '\\'])
+        .filter(|c| !c.is_empty() && *c != "." && *c != "..")
+        .fold(dir.to_path_buf(), |path, c| path.join(sanitize(c)))
+}
+
+async fn extract(source: &impl Source, member: &Member, dir: &Path) -> Result<(), DownloadError> {
+    let path = member_path(member, dir);
+    if member.is_dir() {
+        return Ok(std::fs::create_dir_all(path)?);
+    }
+    let header = source.read_at(member.offset, 30).await?;
+    if u32_at(&header, 0) != Some(LOCAL) {
+        return Err(invalid("bad local header"));
+    }
+    let skip = 30
+        + u16_at(&header, 26).unwrap_or_default() as u64
+        + u16_at(&header, 28).unwrap_or_default() as u64;
+    let (method, size) = (member.method, member.size);
+    let (sender, receiver) = mpsc::channel(2);
+    let writer = tokio::task::spawn_blocking(move || {
+        let pieces = Pieces {
+            receiver,
+            piece: Vec::new(),
+            at: 0,
+        };
+        let mut content: Box<dyn Read> = match method {
+            0 => Box::new(pieces),
+            8 => Box::new(DeflateDecoder::new(pieces)),
+            _ => {
+                return Err(invalid(&format!(
+                    "unsupported compression method {}",
+                    method
+                )));
+            }
+        };
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = File::create(&path)?;
+        let written = io::copy(&mut (&mut content).take(size), &mut file)?;
+        if written != size || content.read(&mut [0])? != 0 {
+            drop(file);
+            let _ = std::fs::remove_file(&path);
+            return Err(invalid("member size does not match the central directory"));
+        }
+        Ok(())
+    });
+    let start = member.offset + skip;
+    let mut at = 0;
+    while at < member.compressed_size {
+        let piece = source
+            .read_at(start + at, PIECE.min(member.compressed_size - at))
+            .await?;
+        if piece.is_empty() {
+            break;
+        }
+        at += piece.len() as u64;
+        if sender.send(piece).await.is_err() {
+            break;
+        }
+    }
+    drop(sender);
+    writer.await?
+}
+
+/// Compressed bytes handed from the async reads to the blocking inflater.
+struct Pieces {
+    receiver: mpsc::Receiver<Vec<u8>>,
+    piece: Vec<u8>,
+    at: usize,
+}
+
+impl Read for Pieces {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.at == self.piece.len() {
+            match self.receiver.blocking_recv() {
+                Some(piece) => (self.piece, self.at) = (piece, 0),
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.piece.len() - self.at);
+        buf[..n].copy_from_slice(&self.piece[self.at..self.at + n]);
+        self.at += n;
+        Ok(n)
+    }
+}
+
+/// Test fixtures shared by the modules that unpack archives.
+#[cfg(test)]
+pub(super) mod fixtures {
+    use super::{CENTRAL, EOCD, LOCAL};
+    use std::path::PathBuf;
+
+    /// An empty folder in the temp dir, unique to `name` and this process.
+    pub fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("stupidl-{}-{}", name, std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// A ZIP of `(name, method, packed data, uncompressed size)` members.
+    pub fn zip(members: &[(&str, u16, &[u8], u32)]) -> Vec<u8> {
+        let (mut zip, mut central) = (Vec::new(), Vec::new());
+        for &(name, method, data, size) in members {
+            let offset = zip.len() as u32;
+            let sizes = [data.len() as u32, size];
+            zip.extend(LOCAL.to_le_bytes());
+            zip.extend([20, 0, 0, 0]);
+            zip.extend(method.to_le_bytes());
+            zip.extend([0; 8]);
+            sizes.iter().for_each(|s| zip.extend(s.to_le_bytes()));
+            zip.extend((name.len() as u16).to_le_bytes());
+            zip.extend([0, 0]);
+            zip.extend(name.as_bytes());
+            zip.extend(data);
+            central.extend(CENTRAL.to_le_bytes());
+            central.extend([20, 0, 20, 0, 0, 0]);
+            central.extend(method.to_le_bytes());
+            central.extend([0; 8]);
+            sizes.iter().for_each(|s| central.extend(s.to_le_bytes()));
+            central.extend((name.len() as u16).to_le_bytes());
+            central.extend([0; 12]);
+            central.extend(offset.to_le_bytes());
+            central.extend(name.as_bytes());
+        }
+        let (offset, size) = (zip.len() as u32, central.len() as u32);
+        zip.extend(central);
+        zip.extend(EOCD.to_le_bytes());
+        zip.extend([0, 0, 0, 0]);
+        zip.extend((members.len() as u16).to_le_bytes());
+        zip.extend((members.len() as u16).to_le_bytes());
+        zip.extend(size.to_le_bytes());
+        zip.extend(offset.to_le_bytes());
+        zip.extend([0, 0]);
+        zip
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{Compression, write::DeflateEncoder};
+    use std::io::Write;
+
+    /// A ZIP with one deflated member whose central directory claims `declared` bytes.
+    fn deflated_zip(name: &str, data: &[u8], declared: u32) -> Vec<u8> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        let packed = encoder.finish().unwrap();
+        fixtures::zip(&[(name, 8, &packed, declared)])
+    }
+
+    fn unzip(name: &str, zip: Vec<u8>) -> (PathBuf, Result<(), DownloadError>) {
+        let dir = fixtures::scratch(name);
+        std::fs::write(dir.join("a.zip"), zip).unwrap();
+        let result = tokio::runtime::Runtime::new()
+            .unwrap()
+            .block_on(extract_zip(&dir.join("a.zip"), &dir.join("out")));
+        (dir, result)
+    }
+
+    #[test]
+    fn inflates_members_of_the_declared_size() {
+        let data = b"stupid ".repeat(1000);
+        let (dir, result) = unzip("inflate", deflated_zip("a.txt", &data, data.len() as u32));
+        result.unwrap();
+        assert_eq!(std::fs::read(dir.join("out/a.txt")).unwrap(), data);
+        std::fs::remove_dir_all(dir).ok();
+    }
+
+    #[test]
+    fn rejects_members_that_inflate_past_their_size() {
+        let data = vec![0; 1 << 20];
+        let (dir, result) = unzip("bomb", deflated_zip("bomb.bin", &data, 1024));
+        assert!(matches!(result, Err(DownloadError::Archive(_))));
+        assert!(!dir.join("out/bomb.bin").exists());
+        std::fs::remove_dir_all(dir).ok();
+        let (dir, result) = unzip("short", deflated_zip("short.bin", &data, 2 << 20));
+        assert!(matches!(result, Err(DownloadError::Archive(_))));
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
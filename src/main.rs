@@ -1,13 +1,154 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 use arboard::Clipboard;
 use eframe::{App, egui};
-use egui::{Pos2, ProgressBar, ViewportBuilder};
-use std::process::exit;
-use stupidownloader::Downloader;
+use egui::{Pos2, ProgressBar, Rect, Sense, Vec2, ViewportBuilder, ViewportCommand};
+use std::{
+    collections::VecDeque, io::Write, path::PathBuf, process::exit, sync::Arc, time::Duration,
+};
+use stupidownloader::{
+    Actions, Blob, Category, Checksum, Comparison, DownloadError, DownloadManager, Downloader,
+    DownloaderConfig, Format, HubFile, Notifier, Plugin, Policy, Progress, ProgressKind, ProxyMode,
+    Record, Release, Sandbox, Scanner, SpeedTest, Status, TEST_URL, Vars, crawl, load_cookies,
+    mirror_path, resolve,
+};
+#[cfg(feature = "scripting")]
+use stupidownloader::{Hooks, Verdict};
 use tokio::runtime::{Builder, Runtime};
 use tokio::sync::watch::Receiver;
+use tokio::task::JoinHandle;
+
+const USAGE: &str = "Usage: stupidownloader [--help]
+       stupidownloader [--dir <dir>] <url>...
+       stupidownloader --plan <url>
+       stupidownloader speedtest [url]
+       stupidownloader mirror <url> [dir] [--accept pattern]... [-x]
+       stupidownloader zip <url> [member]...
+       stupidownloader verify <url> <file>
+       stupidownloader import <list> [dir]
+       stupidownloader history [category]
+
+Downloads the given URLs one after another, or the URL currently on the clipboard.";
+
+const STRIP: Vec2 = Vec2::new(400.0, 18.0);
+
+#[cfg(windows)]
+fn attach_console() {
+    use windows_sys::Win32::System::Console::{ATTACH_PARENT_PROCESS, AttachConsole};
+    unsafe { AttachConsole(ATTACH_PARENT_PROCESS) };
+}
+
+/// Work area (the monitor minus the taskbar) of the monitor nearest to
+/// `point`, in points on the virtual desktop.
+#[cfg(windows)]
+fn work_area(point: Pos2, scale: f32) -> Option<Rect> {
+    use windows_sys::Win32::{
+        Foundation::POINT,
+        Graphics::Gdi::{GetMonitorInfoW, MONITOR_DEFAULTTONEAREST, MONITORINFO, MonitorFromPoint},
+    };
+    let point = POINT {
+        x: (point.x * scale) as i32,
+        y: (point.y * scale) as i32,
+    };
+    let mut info: MONITORINFO = unsafe { std::mem::zeroed() };
+    info.cbSize = size_of::<MONITORINFO>() as u32;
+    let monitor = unsafe { MonitorFromPoint(point, MONITOR_DEFAULTTONEAREST) };
+    if unsafe { GetMonitorInfoW(monitor, &mut info) } == 0 {
+        return None;
+    }
+    let work = info.rcWork;
+    Some(Rect::from_min_max(
+        Pos2::new(work.left as f32, work.top as f32) / scale,
+        Pos2::new(work.right as f32, work.bottom as f32) / scale,
+    ))
+}
+
+/// Other platforms don't say where the monitor sits, so assume it starts
+/// at the origin.
+#[cfg(not(windows))]
+fn work_area(_: Pos2, _: f32) -> Option<Rect> {
+    None
+}
+
+fn speedtest(url: &str) {
+    let client = match DownloaderConfig::new()
+        .proxy(ProxyMode::from_env())
+        .client()
+    {
+        Ok(client) => client,
+        Err(e) => return eprintln!("Speed test failed: {}", e),
+    };
+    match Runtime::new()
+        .unwrap()
+        .block_on(SpeedTest::run(&client, url, Duration::from_secs(3)))
+    {
+        Ok(test) => {
+            let format = Format::from_env();
+            for sample in &test.samples {
+                println!(
+                    "{:>2} connections: {}",
+                    sample.connections,
+                    format.speed(sample.speed)
+                );
+            }
+            println!("Optimal concurrency: {}", test.optimal());
+            if let Ok(exe) = std::env::current_exe()
+                && let Err(e) = test.save(exe.with_file_name(SPEEDTEST))
+            {
+                eprintln!("Could not save the result: {}", e);
+            }
+        }
+        Err(e) => eprintln!("Speed test failed: {}", e),
+    }
+}
 
 fn main() -> Result<(), eframe::Error> {
+    #[cfg(feature = "console")]
+    console_subscriber::init();
+    let mut queue = VecDeque::new();
+    let mut dir = None;
+    if std::env::args().len() > 1 {
+        #[cfg(windows)]
+        attach_console();
+        if std::env::args().any(|a| a == "--help" || a == "-h") {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        if std::env::args().nth(1).as_deref() == Some("speedtest") {
+            speedtest(std::env::args().nth(2).as_deref().unwrap_or(TEST_URL));
+            return Ok(());
+        }
+        if std::env::args().nth(1).as_deref() == Some("--plan") {
+            plan(std::env::args().nth(2).unwrap_or_default());
+            return Ok(());
+        }
+        if std::env::args().nth(1).as_deref() == Some("verify") {
+            verify(std::env::args().skip(2).collect());
+            return Ok(());
+        }
+        if std::env::args().nth(1).as_deref() == Some("zip") {
+            zip(std::env::args().skip(2).collect());
+            return Ok(());
+        }
+        if std::env::args().nth(1).as_deref() == Some("import") {
+            import(std::env::args().skip(2).collect());
+            return Ok(());
+        }
+        if std::env::args().nth(1).as_deref() == Some("history") {
+            history(std::env::args().nth(2));
+            return Ok(());
+        }
+        if std::env::args().nth(1).as_deref() == Some("mirror") {
+            mirror(std::env::args().skip(2).collect());
+            return Ok(());
+        }
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--dir" => dir = args.next().map(PathBuf::from),
+                _ => queue.push_back(arg),
+            }
+        }
+    }
     let options = eframe::NativeOptions {
         viewport: ViewportBuilder::default()
             .with_resizable(false)
@@ -15,64 +156,734 @@ fn main() -> Result<(), eframe::Error> {
             .with_decorations(false)
             .with_taskbar(false)
             .with_transparent(true)
-            .with_inner_size([400.0, 18.0])
+            .with_inner_size(STRIP)
             .with_position(Pos2::ZERO),
         ..Default::default()
     };
     eframe::run_native(
         "StupiDownloader",
         options,
-        Box::new(|_cc| Ok(Box::new(StupidApp::default()))),
+        Box::new(|cc| {
+            let app = StupidApp::launch(queue, dir);
+            app.close_on_ctrl_c(cc.egui_ctx.clone());
+            Ok(Box::new(app))
+        }),
     )
 }
 
+fn plan(url: String) {
+    let app = StupidApp::headless();
+    match app.runtime.block_on(app.setup.clone().prepare(url)) {
+        Ok(Some(downloader)) => println!("{}", downloader.plan()),
+        Ok(None) => println!("Skipped"),
+        Err(e) => eprintln!("Planning failed: {}", e),
+    }
+}
+
+fn verify(args: Vec<String>) {
+    let [url, file] = args.as_slice() else {
+        eprintln!("{}", USAGE);
+        exit(2);
+    };
+    let app = StupidApp::headless();
+    let result = app.runtime.block_on(async {
+        let downloader = Downloader::new_with_config(url, base_config()?).await?;
+        downloader.compare(file).await
+    });
+    match result {
+        Ok(comparison @ Comparison::Identical(_)) => println!("{}", comparison),
+        Ok(comparison) => {
+            println!("{}", comparison);
+            exit(1);
+        }
+        Err(e) => {
+            eprintln!("Verify failed: {}", e);
+            exit(2);
+        }
+    }
+}
+
+fn zip(args: Vec<String>) {
+    let Some((url, wanted)) = args.split_first() else {
+        eprintln!("{}", USAGE);
+        return;
+    };
+    let app = StupidApp::headless();
+    let result = app.runtime.block_on(async {
+        let downloader = Downloader::new_with_config(url, base_config()?).await?;
+        for member in downloader.zip_members().await? {
+            if wanted.is_empty() {
+                println!("{:>12}  {}", member.size, member.name);
+            } else if wanted.contains(&member.name) {
+                println!("{}", member.name);
+                downloader.extract_member(&member, ".".as_ref()).await?;
+            }
+        }
+        Ok::<_, DownloadError>(())
+    });
+    if let Err(e) = result {
+        eprintln!("Zip failed: {}", e);
+    }
+}
+
+/// Lists ended downloads, optionally only those of one category.
+fn history(category: Option<String>) {
+    let category = match category.as_deref().map(Category::parse) {
+        Some(None) => return eprintln!("Unknown category: {}", category.unwrap_or_default()),
+        Some(category) => category,
+        None => None,
+    };
+    let records = match beside_exe(HISTORY).map(Record::load).transpose() {
+        Ok(records) => records.unwrap_or_default(),
+        Err(e) => return eprintln!("Could not read the history: {}", e),
+    };
+    let format = Format::from_env();
+    for record in records
+        .iter()
+        .filter(|r| category.is_none_or(|c| r.category == c))
+    {
+        println!(
+            "{:<10} {:>10}  {}  {}",
+            record.category.as_str(),
+            format.bytes(record.size),
+            record.path,
+            record.status
+        );
+    }
+}
+
+fn import(args: Vec<String>) {
+    let Some(list) = args.first() else {
+        eprintln!("{}", USAGE);
+        return;
+    };
+    let result = Runtime::new().unwrap().block_on(async {
+        let mut config = base_config()?;
+        if let Some(dir) = args.get(1) {
+            config = config.output_dir(dir);
+        }
+        let mut manager = DownloadManager::new(config)?;
+        let total = manager.import_list(list)?;
+        println!("Queued {} downloads", total);
+        let mut failed = 0;
+        loop {
+            for (url, e) in manager.pump().await? {
+                eprintln!("\n{}: {}", url, e);
+                failed += 1;
+            }
+            if manager.queued().all(|q| q.paused) && manager.active() == 0 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            let status = format!(
+                "{} running, {} queued, {}",
+                manager.active(),
+                manager.queued().count(),
+                describe(&manager.progress())
+            );
+            print!("\r{:<60}", status);
+            std::io::stdout().flush().ok();
+        }
+        println!();
+        for (_, downloader) in manager.list() {
+            if downloader.watcher().borrow().status == Status::Failed {
+                eprintln!("{}: failed", downloader.url);
+                failed += 1;
+            }
+        }
+        let summary = Vars::new()
+            .with(
+                "status",
+                match failed {
+                    0 => "Completed".to_owned(),
+                    n => format!("{} of {} failed", n, total),
+                },
+            )
+            .with("path", list.as_str())
+            .with("host", format!("{} downloads", total));
+        let client = manager.config().client()?;
+        for notifier in Notifier::from_env() {
+            if let Err(e) = notifier.send(&client, &summary).await {
+                eprintln!("{}", e);
+            }
+        }
+        Ok::<_, DownloadError>(())
+    });
+    if let Err(e) = result {
+        eprintln!("Import failed: {}", e);
+    }
+}
+
+fn mirror(args: Vec<String>) {
+    let mut accept = Vec::new();
+    let mut hosts = false;
+    let mut positional = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--accept" | "-A" => accept.extend(args.next()),
+            "--force-directories" | "-x" => hosts = true,
+            _ => positional.push(arg),
+        }
+    }
+    let Some(url) = positional.first() else {
+        eprintln!("{}", USAGE);
+        return;
+    };
+    let dir = PathBuf::from(positional.get(1).map_or(".", String::as_str));
+    let result = Runtime::new().unwrap().block_on(async {
+        let config = base_config()?;
+        let entries = crawl(&config.client()?, url, &accept).await?;
+        for (i, entry) in entries.iter().enumerate() {
+            println!("[{}/{}] {}", i + 1, entries.len(), entry.path.display());
+            let output = match hosts {
+                true => dir.join(mirror_path(&entry.url).unwrap_or(entry.path.clone())),
+                false => dir.join(&entry.path),
+            };
+            let fetched = async {
+                if let Some(parent) = output.parent() {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                let mut downloader =
+                    Downloader::new_with_config(&entry.url, config.clone()).await?;
+                downloader.set_output(&output)?;
+                downloader.start();
+                while downloader.running() {
+                    tokio::time::sleep(Duration::from_millis(500)).await;
+                    print!("\r{:<40}", describe(&downloader.watcher().borrow()));
+                    std::io::stdout().flush().ok();
+                }
+                println!();
+                downloader.join().await
+            };
+            if let Err(e) = fetched.await {
+                eprintln!("{}: {}", entry.url, e);
+            }
+        }
+        Ok::<_, DownloadError>(())
+    });
+    if let Err(e) = result {
+        eprintln!("Mirror failed: {}", e);
+    }
+}
+
+#[cfg(feature = "console")]
+async fn report_metrics(handle: tokio::runtime::Handle) {
+    let mut interval = tokio::time::interval(Duration::from_secs(5));
+    loop {
+        interval.tick().await;
+        let metrics = handle.metrics();
+        eprintln!(
+            "workers: {}, tasks: {}, queued: {}",
+            metrics.num_workers(),
+            metrics.num_alive_tasks(),
+            metrics.global_queue_depth()
+        );
+    }
+}
+
+fn describe(progress: &Progress) -> String {
+    if progress.status == Status::WaitingForSpace {
+        return "Waiting for space in the quota".to_owned();
+    }
+    match progress.kind() {
+        ProgressKind::Determinate(fraction) => format!("{:.1}%", fraction * 100.0),
+        ProgressKind::Indeterminate => {
+            let format = Format::from_env();
+            format!(
+                "{} at {}",
+                format.bytes(progress.downloaded),
+                format.speed(progress.speed)
+            )
+        }
+    }
+}
+
+enum SaveDialog {
+    Always,
+    WhenAmbiguous,
+    Never,
+}
+
+impl SaveDialog {
+    fn from_env() -> Self {
+        match std::env::var("STUPIDL_SAVE_DIALOG").as_deref() {
+            Ok("always") => Self::Always,
+            Ok("never") => Self::Never,
+            _ => Self::WhenAmbiguous,
+        }
+    }
+
+    fn ask(&self, name: &str) -> Option<String> {
+        let ambiguous = name == "Download" || !name.contains('.');
+        match self {
+            Self::Always => {}
+            Self::WhenAmbiguous if ambiguous => {}
+            _ => return Some(name.to_owned()),
+        }
+        rfd::FileDialog::new()
+            .set_file_name(name)
+            .save_file()
+            .map(|p| p.to_string_lossy().into_owned())
+    }
+}
+
+const SPEEDTEST: &str = "speedtest.json";
+const HISTORY: &str = "history.jsonl";
+
+/// Base config for every download, calibrated by the last `speedtest` and
+/// bound by `policy.json` and `STUPIDL_SANDBOX`.
+fn base_config() -> Result<DownloaderConfig, DownloadError> {
+    let mut config = DownloaderConfig::new().proxy(ProxyMode::from_env());
+    if let Some(test) = beside_exe(SPEEDTEST).and_then(SpeedTest::load) {
+        config = config.calibrate(&test);
+    }
+    if std::env::var_os("STUPIDL_AUTO_TUNE").is_some() {
+        config = config.auto_tune(true);
+    }
+    if let Some(policy) = beside_exe("policy.json") {
+        config = config.policy(Policy::load(policy)?);
+    }
+    if let Some(sandbox) = Sandbox::from_env()? {
+        config = config.sandbox(sandbox);
+    }
+    Ok(config)
+}
+
+fn beside_exe(name: &str) -> Option<PathBuf> {
+    let path = std::env::current_exe().ok()?.with_file_name(name);
+    path.exists().then_some(path)
+}
+
 struct StupidApp {
-    _runtime: Runtime,
-    downloader: Downloader,
-    tracer: Receiver<u64>,
+    runtime: Runtime,
+    clipboard: Option<Clipboard>,
+    interactive: bool,
+    seen: Option<String>,
+    queue: VecDeque<String>,
+    setup: Setup,
+    job: Option<Job>,
+    layout: Option<(f32, Vec2)>,
+    notifiers: Arc<Vec<Notifier>>,
+}
+
+/// Network, disk and script work runs on the runtime, so the strip keeps
+/// drawing; `update` polls the handles each frame.
+enum Job {
+    Preparing(JoinHandle<Result<Option<Downloader>, DownloadError>>),
+    Running(Downloader, Receiver<Progress>),
+    /// Scanning, running the completion actions and notifying.
+    Finishing(JoinHandle<()>),
+}
+
+/// What preparing a download takes from the app, cloned into the task.
+#[derive(Clone)]
+struct Setup {
+    dir: Option<PathBuf>,
+    #[cfg(feature = "scripting")]
+    hooks: Option<Arc<Hooks>>,
 }
 
-impl Default for StupidApp {
-    fn default() -> Self {
+impl StupidApp {
+    fn launch(queue: VecDeque<String>, dir: Option<PathBuf>) -> Self {
+        let mut app = Self {
+            clipboard: Clipboard::new().ok(),
+            interactive: true,
+            queue,
+            ..Self::headless()
+        };
+        app.setup.dir = dir;
+        if app.queue.is_empty() {
+            app.poll_clipboard();
+        } else {
+            let text = app.clipboard.as_mut().and_then(|c| c.get_text().ok());
+            app.seen = text.map(|t| t.trim().to_owned());
+        }
+        app
+    }
+
+    /// Ctrl+C closes the window like any other exit, so `on_exit` gets to
+    /// save the running download.
+    fn close_on_ctrl_c(&self, ctx: egui::Context) {
+        self.runtime.spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                ctx.send_viewport_cmd(ViewportCommand::Close);
+                ctx.request_repaint();
+            }
+        });
+    }
+
+    /// A new scale factor or monitor layout can leave the undecorated strip
+    /// off-screen; pull it back inside the monitor's work area at its
+    /// normal size.
+    fn keep_on_screen(&mut self, ctx: &egui::Context) {
+        let (scale, viewport) = ctx.input(|i| (i.pixels_per_point, i.viewport().clone()));
+        let (Some(monitor), Some(outer)) = (viewport.monitor_size, viewport.outer_rect) else {
+            return;
+        };
+        let layout = (scale, monitor);
+        if self
+            .layout
+            .replace(layout)
+            .is_none_or(|last| last == layout)
+        {
+            return;
+        }
+        let area =
+            work_area(outer.center(), scale).unwrap_or(Rect::from_min_size(Pos2::ZERO, monitor));
+        let max = (area.max - STRIP).max(area.min);
+        ctx.send_viewport_cmd(ViewportCommand::InnerSize(STRIP));
+        ctx.send_viewport_cmd(ViewportCommand::OuterPosition(
+            outer.min.clamp(area.min, max),
+        ));
+    }
+
+    /// Top-left corner of the work area of the monitor the strip is on.
+    fn corner(ctx: &egui::Context) -> Pos2 {
+        let (scale, outer) = ctx.input(|i| (i.pixels_per_point, i.viewport().outer_rect));
+        outer
+            .and_then(|outer| work_area(outer.center(), scale))
+            .map_or(Pos2::ZERO, |area| area.min)
+    }
+
+    fn headless() -> Self {
         let runtime = Builder::new_multi_thread()
             .worker_threads(16)
             .global_queue_interval(11)
             .enable_all()
             .build()
             .unwrap();
-        let (tracer, downloader) = runtime.block_on(async {
-            let mut downloader = Downloader::new(&Clipboard::new().unwrap().get_text().unwrap())
-                .await
-                .unwrap();
-            downloader.start();
-            (downloader.watcher(), downloader)
-        });
+        #[cfg(feature = "console")]
+        if std::env::var_os("STUPIDL_METRICS").is_some() {
+            runtime.spawn(report_metrics(runtime.handle().clone()));
+        }
         Self {
-            _runtime: runtime,
-            downloader,
-            tracer,
+            runtime,
+            clipboard: None,
+            interactive: false,
+            seen: None,
+            queue: VecDeque::new(),
+            setup: Setup {
+                dir: None,
+                #[cfg(feature = "scripting")]
+                // A broken script is reported and the app runs without hooks
+                hooks: beside_exe("hooks.rhai").and_then(|p| match Hooks::load(p) {
+                    Ok(hooks) => Some(Arc::new(hooks)),
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        None
+                    }
+                }),
+            },
+            job: None,
+            layout: None,
+            notifiers: Arc::new(Notifier::from_env()),
         }
     }
+
+    fn poll_clipboard(&mut self) {
+        let text = self.clipboard.as_mut().and_then(|c| c.get_text().ok());
+        if let Some(text) = text.map(|t| t.trim().to_owned())
+            && self.seen.as_ref() != Some(&text)
+        {
+            self.seen = Some(text.clone());
+            self.submit(text);
+        }
+    }
+
+    fn submit(&mut self, url: String) {
+        if reqwest::Url::parse(&url).is_err() {
+            return;
+        }
+        let setup = self.setup.clone();
+        self.job = Some(Job::Preparing(self.runtime.spawn(setup.prepare(url))));
+    }
+
+    /// Asks where to save when no directory was given, then starts.
+    fn run(&mut self, mut downloader: Downloader) {
+        if self.interactive && self.setup.dir.is_none() {
+            let Some(output) = SaveDialog::from_env().ask(&downloader.output) else {
+                return;
+            };
+            if let Err(e) = downloader.set_output(output) {
+                return eprintln!("{}", e);
+            }
+        }
+        let _runtime = self.runtime.enter();
+        downloader.start();
+        let tracer = downloader.watcher();
+        self.job = Some(Job::Running(downloader, tracer));
+    }
+
+    /// Moves the job along once its task is done.
+    fn poll_job(&mut self) {
+        match self.job.take() {
+            Some(Job::Preparing(handle)) if handle.is_finished() => {
+                match self.runtime.block_on(handle) {
+                    Ok(Ok(Some(downloader))) => self.run(downloader),
+                    Ok(Ok(None)) => {}
+                    Ok(Err(e)) => eprintln!("{}", e),
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+            Some(Job::Running(downloader, _)) if !downloader.running() => self.finish(downloader),
+            Some(Job::Finishing(handle)) if handle.is_finished() => {
+                if let Err(e) = self.runtime.block_on(handle) {
+                    eprintln!("{}", e);
+                }
+            }
+            job => self.job = job,
+        }
+    }
+
+    fn dropped(ctx: &egui::Context) -> Option<String> {
+        ctx.input(|i| {
+            i.events
+                .iter()
+                .find_map(|e| match e {
+                    egui::Event::Paste(text) => Some(text.trim().to_owned()),
+                    _ => None,
+                })
+                .or_else(|| {
+                    let path = i.raw.dropped_files.first()?.path.as_ref()?;
+                    std::fs::read_to_string(path)
+                        .ok()?
+                        .lines()
+                        .map(|l| l.trim().trim_start_matches("URL="))
+                        .find(|l| reqwest::Url::parse(l).is_ok())
+                        .map(str::to_owned)
+                })
+        })
+    }
+
+    /// Hands the ended download to a task that scans, runs the completion
+    /// actions and notifies, so slow scanners or mail servers don't freeze
+    /// the strip.
+    fn finish(&mut self, mut downloader: Downloader) {
+        let notifiers = self.notifiers.clone();
+        #[cfg(feature = "scripting")]
+        let hooks = self.setup.hooks.clone();
+        let task = async move {
+            let mut finished = None;
+            let result = async {
+                downloader.join().await?;
+                // Built after join, which moves `output` to a renamed collision
+                let vars = finished.insert(downloader.vars());
+                if let Some(scanner) = Scanner::from_env() {
+                    scanner.scan(vars).await?;
+                }
+                if let Some(actions) = beside_exe("actions.json") {
+                    Actions::load(actions)?.run(vars).await?;
+                }
+                Ok::<_, DownloadError>(())
+            }
+            .await;
+            let mut vars = finished.unwrap_or_else(|| downloader.vars());
+            vars.set(
+                "status",
+                match result {
+                    Ok(()) => "Completed".to_owned(),
+                    Err(e) => format!("Failed ({})", e),
+                },
+            );
+            let record = Record::new(
+                &downloader.url,
+                vars.get("path").unwrap_or_default(),
+                downloader.category(),
+                downloader.watcher().borrow().total,
+                vars.get("status").unwrap_or_default(),
+            );
+            if let Ok(exe) = std::env::current_exe()
+                && let Err(e) = record.append(exe.with_file_name(HISTORY))
+            {
+                eprintln!("Could not record the download: {}", e);
+            }
+            #[cfg(feature = "scripting")]
+            if let Some(hooks) = &hooks
+                && let Err(e) = hooks.on_complete(&vars)
+            {
+                eprintln!("{}", e);
+            }
+            // Notifications go out through the same proxy as downloads
+            let client = match base_config().and_then(|c| Ok(c.client()?)) {
+                Ok(client) => client,
+                Err(e) => return eprintln!("{}", e),
+            };
+            for notifier in notifiers.iter() {
+                if let Err(e) = notifier.send(&client, &vars).await {
+                    eprintln!("{}", e);
+                }
+            }
+        };
+        self.job = Some(Job::Finishing(self.runtime.spawn(task)));
+    }
+}
+
+impl Setup {
+    async fn prepare(self, url: String) -> Result<Option<Downloader>, DownloadError> {
+        #[cfg(feature = "scripting")]
+        let url = match self
+            .hooks
+            .as_ref()
+            .map(|h| h.on_url_added(&url))
+            .transpose()?
+        {
+            Some(Verdict::Skip) => return Ok(None),
+            Some(Verdict::Replace(url)) => url,
+            _ => url,
+        };
+        let plugins = beside_exe("plugins")
+            .map(Plugin::discover)
+            .unwrap_or_default();
+        let mut config = base_config()?.trace(std::env::var_os("STUPIDL_TRACE").is_some());
+        if let Ok(charset) = std::env::var("STUPIDL_CHARSET") {
+            config = config.charset(&charset);
+        }
+        if let Some(rate) = std::env::var("STUPIDL_CONNECTION_RATE")
+            .ok()
+            .and_then(|r| r.trim().parse::<u64>().ok())
+        {
+            config = config.connection_rate(rate * 1024);
+        }
+        if let Ok(quotas) = std::env::var("STUPIDL_QUOTA") {
+            for (dir, mb) in quotas.split(';').filter_map(|q| q.rsplit_once('=')) {
+                if let Ok(mb) = mb.trim().parse::<u64>() {
+                    config = config.quota(dir.trim(), mb * 1024 * 1024);
+                }
+            }
+        }
+        if let Some(dir) = &self.dir {
+            config = config.output_dir(dir);
+        }
+        if let Some(cookies) = beside_exe("cookies.txt") {
+            config = config.cookie_jar(load_cookies(cookies)?);
+        }
+        // Lookups go through the same proxy and cookies as the download
+        let resolved = if let Some(release) = Release::parse(&url) {
+            Some(release.resolve(&config.client()?).await?)
+        } else if let Some(blob) = Blob::parse(&url) {
+            Some(blob.resolve(&config.client()?).await?)
+        } else if let Some(file) = HubFile::parse(&url) {
+            Some(file.resolve(&config.manual_client()?).await?)
+        } else {
+            match resolve(&plugins, &url).await {
+                Ok(resolved) => resolved,
+                // A broken plugin leaves the URL to download as it is
+                Err(e) => {
+                    eprintln!("{}", e);
+                    None
+                }
+            }
+        };
+        let mut downloader = match &resolved {
+            Some(resolved) => {
+                let config = config.headers(resolved.header_map()?);
+                let urls: Vec<_> = resolved.urls.iter().map(String::as_str).collect();
+                Downloader::new_multi_with_config(&urls, config).await?
+            }
+            None => Downloader::new_with_config(&url, config).await?,
+        };
+        if let Some(checksum) = resolved
+            .and_then(|r| r.checksum)
+            .and_then(|c| Checksum::parse(&c))
+        {
+            downloader.expect(checksum);
+        }
+        #[cfg(feature = "scripting")]
+        match self
+            .hooks
+            .as_ref()
+            .map(|h| h.on_probe(&downloader.vars()))
+            .transpose()?
+        {
+            Some(Verdict::Skip) => return Ok(None),
+            Some(Verdict::Replace(output)) => downloader.set_output(output)?,
+            _ => {}
+        }
+        Ok(Some(downloader))
+    }
 }
 
 impl App for StupidApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        egui::Area::new("area".into())
+        self.keep_on_screen(ctx);
+        self.poll_job();
+        if self.job.is_none() {
+            match self.queue.pop_front().or_else(|| Self::dropped(ctx)) {
+                Some(url) => self.submit(url),
+                None => self.poll_clipboard(),
+            }
+        }
+        let add = egui::Area::new("area".into())
             .fixed_pos(Pos2::ZERO)
-            .show(ctx, |ui| {
-                if self.downloader.running() {
-                    ui.add(
-                        ProgressBar::new(self.tracer.borrow().clone() as f32 / 100.0)
-                            .show_percentage()
-                            .animate(true),
+            .show(ctx, |ui| match &self.job {
+                Some(Job::Preparing(_)) => {
+                    ui.add(ProgressBar::new(0.0).text("Preparing").animate(true));
+                    false
+                }
+                Some(Job::Finishing(_)) => {
+                    ui.add(ProgressBar::new(1.0).text("Finishing").animate(true));
+                    false
+                }
+                Some(Job::Running(downloader, tracer)) => {
+                    let progress = *tracer.borrow();
+                    let bar = ui.add(
+                        match progress.kind() {
+                            _ if progress.status == Status::WaitingForSpace => {
+                                ProgressBar::new(0.0).text(describe(&progress))
+                            }
+                            ProgressKind::Determinate(fraction) => {
+                                ProgressBar::new(fraction as f32).show_percentage()
+                            }
+                            ProgressKind::Indeterminate => {
+                                ProgressBar::new(0.0).text(describe(&progress))
+                            }
+                        }
+                        .animate(true),
                     );
-                } else {
-                    exit(0)
+                    let response = ui.interact(bar.rect, ui.id().with("job"), Sense::click());
+                    if response.middle_clicked() {
+                        ctx.send_viewport_cmd(ViewportCommand::OuterPosition(Self::corner(ctx)));
+                    }
+                    if response.secondary_clicked()
+                        && let Some(clipboard) = &mut self.clipboard
+                    {
+                        clipboard.set_text(downloader.diagnostics()).ok();
+                    }
+                    false
+                }
+                None => {
+                    let bar =
+                        ui.add(ProgressBar::new(0.0).text("Add URL: copy, paste or drop it here"));
+                    let response = ui.interact(bar.rect, ui.id().with("idle"), Sense::click());
+                    if response.middle_clicked() {
+                        ctx.send_viewport_cmd(ViewportCommand::OuterPosition(Self::corner(ctx)));
+                    }
+                    if response.secondary_clicked() {
+                        exit(0)
+                    }
+                    response.clicked()
                 }
-            });
+            })
+            .inner;
+        if add {
+            self.seen = None;
+            self.poll_clipboard();
+        }
 
         ctx.request_repaint_after(std::time::Duration::from_millis(100));
     }
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        match self.job.take() {
+            Some(Job::Running(mut downloader, _)) => {
+                self.runtime.block_on(downloader.shutdown()).ok();
+            }
+            // Lets a move or extraction finish rather than leave it halfway
+            Some(Job::Finishing(handle)) => drop(self.runtime.block_on(handle)),
+            _ => {}
+        }
+    }
+
     fn clear_color(&self, _: &egui::Visuals) -> [f32; 4] {
         [0.0, 0.0, 0.0, 0.0]
     }
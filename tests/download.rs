@@ -0,0 +1,404 @@
+use reqwest::header::{CONTENT_LENGTH, CONTENT_RANGE};
+use std::{
+    path::PathBuf,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::Duration,
+};
+use stupidownloader::{
+    Algorithm, Checksum, Comparison, DownloadError, DownloadManager, Downloader, DownloaderConfig,
+    Notifier, Outcome, Policy, Priority, Protocol, ProxyMode, Sandbox, SpeedTest, Status, Vars,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// A one-file HTTP/1.1 server: answers HEAD and ranged GETs for `body`
+/// with `status`, pausing `delay` between 16 KiB writes. Also returns how
+/// many requests it has answered.
+async fn serve(status: u16, body: Vec<u8>, delay: Duration) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = requests.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            counter.fetch_add(1, Ordering::Relaxed);
+            tokio::spawn(respond(stream, status, body.clone(), delay));
+        }
+    });
+    (url, requests)
+}
+
+async fn respond(mut stream: TcpStream, status: u16, body: Vec<u8>, delay: Duration) {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+        match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => request.extend_from_slice(&buffer[..n]),
+        }
+    }
+    let request = String::from_utf8_lossy(&request).to_ascii_lowercase();
+    let range = request
+        .lines()
+        .find_map(|l| l.strip_prefix("range: bytes="))
+        .and_then(|r| r.split_once('-'))
+        .map(|(start, end)| {
+            let start: usize = start.trim().parse().unwrap_or(0);
+            let end = end.trim().parse().unwrap_or(body.len() - 1);
+            (start, end.min(body.len() - 1))
+        });
+    let (status, content, extra) = match range {
+        Some((start, _)) if status == 200 && start >= body.len() => (
+            416,
+            &body[..0],
+            format!("Content-Range: bytes */{}\r\n", body.len()),
+        ),
+        Some((start, end)) if status == 200 => (
+            206,
+            &body[start..=end],
+            format!("Content-Range: bytes {}-{}/{}\r\n", start, end, body.len()),
+        ),
+        _ => (status, &body[..], String::new()),
+    };
+    let head = format!(
+        "HTTP/1.1 {} X\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\n{}Connection: close\r\n\r\n",
+        status,
+        content.len(),
+        extra
+    );
+    if stream.write_all(head.as_bytes()).await.is_err() || request.starts_with("head") {
+        return;
+    }
+    for piece in content.chunks(16 * 1024) {
+        if stream.write_all(piece).await.is_err() {
+            return;
+        }
+        tokio::time::sleep(delay).await;
+    }
+}
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("stupidl-{}-{}", name, std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn config(dir: &PathBuf) -> DownloaderConfig {
+    DownloaderConfig::new()
+        .proxy(ProxyMode::Direct)
+        .output_dir(dir)
+        .auto_tune(false)
+}
+
+#[tokio::test]
+async fn error_pages_fail_the_probe() {
+    let dir = scratch("error-page");
+    let (url, _) = serve(404, b"<html>not found</html>".to_vec(), Duration::ZERO).await;
+    let result = Downloader::new_with_config(&url, config(&dir)).await;
+    assert!(matches!(result, Err(DownloadError::HttpRequest(_))));
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[tokio::test]
+async fn forced_http2_does_not_fall_back() {
+    let dir = scratch("http2");
+    let (url, _) = serve(200, b"payload".to_vec(), Duration::ZERO).await;
+    let config = config(&dir).protocol(Protocol::Http2);
+    let result = Downloader::new_with_config(&url, config).await;
+    assert!(matches!(result, Err(DownloadError::HttpRequest(_))));
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[tokio::test]
+async fn denied_hosts_are_never_contacted() {
+    let dir = scratch("denied");
+    let (url, requests) = serve(200, b"payload".to_vec(), Duration::ZERO).await;
+    let policy = Policy {
+        deny_hosts: vec!["127.0.0.1".to_owned()],
+        ..Default::default()
+    };
+    let result = Downloader::new_with_config(&url, config(&dir).policy(policy)).await;
+    assert!(matches!(result, Err(DownloadError::PolicyViolation(_))));
+    assert_eq!(requests.load(Ordering::Relaxed), 0);
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[tokio::test]
+async fn renamed_outputs_are_checked_against_the_policy() {
+    let dir = scratch("policy-rename");
+    let (url, _) = serve(200, b"payload".to_vec(), Duration::ZERO).await;
+    let policy = Policy {
+        deny_extensions: vec![".exe".to_owned()],
+        ..Default::default()
+    };
+    let mut downloader = Downloader::new_with_config(&url, config(&dir).policy(policy))
+        .await
+        .unwrap();
+    let output = downloader.output.clone();
+    assert!(matches!(
+        downloader.set_output("setup.exe"),
+        Err(DownloadError::PolicyViolation(_))
+    ));
+    assert_eq!(downloader.output, output);
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[tokio::test]
+async fn outputs_stay_inside_the_sandbox() {
+    let dir = scratch("sandbox");
+    std::fs::create_dir_all(dir.join("inside")).unwrap();
+    let (url, _) = serve(200, b"payload".to_vec(), Duration::ZERO).await;
+    let sandbox = Sandbox::new([dir.join("inside")]).unwrap();
+    let config = config(&dir).sandbox(sandbox);
+    let result = Downloader::new_with_config(&url, config.clone()).await;
+    assert!(matches!(result, Err(DownloadError::OutsideSandbox(_))));
+    let inside = config.output_dir(dir.join("inside"));
+    let mut downloader = Downloader::new_with_config(&url, inside).await.unwrap();
+    assert!(downloader.set_output(dir.join("escaped.bin")).is_err());
+    // Assigning the field directly is caught before anything is written
+    downloader.output = dir.join("escaped.bin").to_string_lossy().into_owned();
+    downloader.start();
+    assert!(matches!(
+        downloader.join().await,
+        Err(DownloadError::OutsideSandbox(_))
+    ));
+    assert!(!dir.join("escaped.bin").exists());
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[tokio::test]
+async fn cancel_deletes_downloads_that_failed_on_their_own() {
+    let dir = scratch("cancel-failed");
+    let (url, _) = serve(200, b"payload".to_vec(), Duration::ZERO).await;
+    let config = config(&dir);
+    let mut downloader = Downloader::new_with_config(&url, config.clone())
+        .await
+        .unwrap();
+    downloader.expect(Checksum::new(Algorithm::Sha256, &"0".repeat(64)));
+    let mut watcher = downloader.watcher();
+    downloader.start();
+    watcher
+        .wait_for(|p| p.status == Status::Failed)
+        .await
+        .unwrap();
+    let part = config.artifacts.path_for(&downloader.output, "part");
+    assert!(part.exists());
+    assert!(matches!(
+        downloader.cancel(true).await,
+        Err(DownloadError::ChecksumMismatch { .. })
+    ));
+    assert!(!part.exists());
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[tokio::test]
+async fn mirrors_must_answer_the_probe_like_the_primary() {
+    let dir = scratch("mirrors");
+    let body = vec![7; 1024 * 1024];
+    let (primary, _) = serve(200, body.clone(), Duration::ZERO).await;
+    let (same, _) = serve(200, body.clone(), Duration::ZERO).await;
+    // Whole file with the right length, but no ranges
+    let (unranged, _) = serve(203, body.clone(), Duration::ZERO).await;
+    let (shorter, _) = serve(200, body[1..].to_vec(), Duration::ZERO).await;
+    let urls = [&*primary, &*same, &*unranged, &*shorter];
+    let downloader = Downloader::new_multi_with_config(&urls, config(&dir))
+        .await
+        .unwrap();
+    assert_eq!(downloader.mirrors, [primary, same]);
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[tokio::test]
+async fn removing_a_failed_download_deletes_its_files() {
+    let dir = scratch("remove-failed");
+    // Answers the probe, then hangs up on every chunk request
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/file.bin", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        if let Ok((stream, _)) = listener.accept().await {
+            respond(stream, 200, vec![7; 1024 * 1024], Duration::ZERO).await;
+        }
+        while let Ok((stream, _)) = listener.accept().await {
+            drop(stream);
+        }
+    });
+    let config = config(&dir).retries(0);
+    let mut manager = DownloadManager::new(config.clone()).unwrap();
+    let mut downloader = Downloader::new_with_config(&url, config.clone())
+        .await
+        .unwrap();
+    let (part, state) = (
+        config.artifacts.path_for(&downloader.output, "part"),
+        config.artifacts.path_for(&downloader.output, "stdl"),
+    );
+    let mut watcher = downloader.watcher();
+    downloader.start();
+    let id = manager.insert(downloader);
+    watcher
+        .wait_for(|p| p.status == Status::Failed)
+        .await
+        .unwrap();
+    assert!(part.exists() && state.exists());
+    manager.remove(id, true).await.unwrap();
+    assert!(!part.exists());
+    assert!(!state.exists());
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[tokio::test]
+async fn sha256_is_known_without_an_expected_checksum() {
+    let dir = scratch("sha256");
+    let cases = [
+        (
+            b"payload".to_vec(),
+            "239f59ed55e737c77147cf55ad0c1b030b6d7ee748a7426952f9b852d5a935e5",
+        ),
+        (
+            vec![7; 1024 * 1024],
+            "51b12eb838732b786b4d45c660a974ddf3860ae09084fd293fa6e5df46581a6c",
+        ),
+    ];
+    for (i, (body, sha256)) in cases.into_iter().enumerate() {
+        let (url, _) = serve(200, body, Duration::ZERO).await;
+        let mut downloader = Downloader::new_with_config(&url, config(&dir))
+            .await
+            .unwrap();
+        downloader.set_output(format!("{}.bin", i)).unwrap();
+        downloader.start();
+        downloader.join().await.unwrap();
+        assert_eq!(downloader.vars().render("{sha256}"), sha256);
+    }
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[tokio::test]
+async fn notifier_errors_hide_the_url() {
+    let (url, _) = serve(500, b"down".to_vec(), Duration::ZERO).await;
+    let notifier = Notifier::Discord {
+        webhook: format!("{}?token=secret", url),
+        template: "{status}".to_owned(),
+    };
+    let error = notifier
+        .send(&reqwest::Client::new(), &Vars::new())
+        .await
+        .unwrap_err();
+    assert!(!error.to_string().contains("secret"));
+}
+
+#[tokio::test]
+async fn dropping_the_watcher_never_kills_the_download() {
+    let dir = scratch("watcher");
+    let body: Vec<u8> = (0..2 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let (url, _) = serve(200, body.clone(), Duration::from_millis(2)).await;
+    let config = config(&dir).chunk_size(256 * 1024).max_parallel(4);
+    let mut downloader = Downloader::new_with_config(&url, config).await.unwrap();
+    let mut watcher = downloader.watcher();
+    let handle = downloader.handle();
+    downloader.start();
+    watcher.wait_for(|p| p.downloaded > 0).await.unwrap();
+    // Every observer goes away mid-transfer
+    drop(watcher);
+    drop(handle);
+    assert_eq!(downloader.join().await.unwrap(), Outcome::Complete);
+    assert_eq!(std::fs::read(&downloader.output).unwrap(), body);
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[tokio::test]
+async fn small_files_take_a_single_request() {
+    let dir = scratch("small");
+    let body = b"tiny file".repeat(100);
+    let (url, requests) = serve(200, body.clone(), Duration::ZERO).await;
+    let mut downloader = Downloader::new_with_config(&url, config(&dir))
+        .await
+        .unwrap();
+    downloader.start();
+    assert_eq!(downloader.join().await.unwrap(), Outcome::Complete);
+    assert_eq!(std::fs::read(&downloader.output).unwrap(), body);
+    assert_eq!(requests.load(Ordering::Relaxed), 1);
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[tokio::test]
+async fn headers_describe_the_whole_file() {
+    let dir = scratch("headers");
+    let body = vec![7; 1024 * 1024];
+    let (url, _) = serve(200, body.clone(), Duration::ZERO).await;
+    let downloader = Downloader::new_with_config(&url, config(&dir))
+        .await
+        .unwrap();
+    let headers = downloader.headers();
+    assert_eq!(headers[CONTENT_LENGTH], body.len().to_string().as_str());
+    assert!(!headers.contains_key(CONTENT_RANGE));
+    std::fs::write(dir.join("copy.bin"), &body).unwrap();
+    assert!(matches!(
+        downloader.compare(dir.join("copy.bin")).await.unwrap(),
+        Comparison::Identical(_)
+    ));
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[tokio::test]
+async fn list_names_land_beside_the_download() {
+    let dir = scratch("import");
+    let (url, _) = serve(200, b"listed".to_vec(), Duration::ZERO).await;
+    let list = dir.join("list.txt");
+    std::fs::write(&list, format!("{}\n  out=../../escaped.bin\n", url)).unwrap();
+    let downloads = dir.join("downloads");
+    let mut manager = DownloadManager::new(config(&downloads)).unwrap();
+    manager.import_list(&list).unwrap();
+    assert!(manager.run().await.unwrap().is_empty());
+    let (_, downloader) = manager.list().next().unwrap();
+    let output = std::path::absolute(&downloader.output).unwrap();
+    assert_eq!(
+        output,
+        std::path::absolute(downloads.join("escaped.bin")).unwrap()
+    );
+    assert_eq!(std::fs::read(output).unwrap(), b"listed");
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[tokio::test]
+async fn speed_tests_stay_inside_small_files() {
+    let body: Vec<u8> = (0..100 * 1024).map(|i| (i % 251) as u8).collect();
+    let (url, _) = serve(200, body, Duration::ZERO).await;
+    let client = DownloaderConfig::new()
+        .proxy(ProxyMode::Direct)
+        .client()
+        .unwrap();
+    let test = SpeedTest::run(&client, &url, Duration::from_millis(100))
+        .await
+        .unwrap();
+    assert_eq!(test.samples.len(), 6);
+    assert!(test.samples.iter().all(|s| s.speed > 0.0));
+}
+
+#[tokio::test]
+async fn urgent_downloads_hold_back_the_lowest_priority() {
+    let dir = scratch("preempt");
+    let body: Vec<u8> = (0..2 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let (url, _) = serve(200, body.clone(), Duration::from_millis(10)).await;
+    let mut manager = DownloadManager::new(config(&dir)).unwrap();
+    manager.set_max_connections(1);
+    let low = manager
+        .add_with_priority(&url.replace("file.bin", "low.bin"), Priority::Low)
+        .await
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    manager
+        .add_with_priority(&url.replace("file.bin", "high.bin"), Priority::High)
+        .await
+        .unwrap();
+    manager.pump().await.unwrap();
+    assert_eq!(manager.get(low).unwrap().max_parallel(), 1);
+    assert!(manager.run().await.unwrap().is_empty());
+    assert_eq!(std::fs::read(dir.join("low.bin")).unwrap(), body);
+    assert_eq!(std::fs::read(dir.join("high.bin")).unwrap(), body);
+    std::fs::remove_dir_all(dir).ok();
+}